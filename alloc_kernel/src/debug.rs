@@ -0,0 +1,135 @@
+//! Heap debugging, enabled with the `debug` feature. Every allocation gets a
+//! header with red zones on both sides, the header links all live allocations
+//! together, and freed memory is poisoned.
+
+use alloc::heap::Layout;
+use core::{cmp, mem, ptr};
+use spin::Mutex;
+
+const RED_ZONE_SIZE: usize = 16;
+const RED_ZONE_BYTE: u8 = 0xAB;
+/// Freed memory is filled with this, so use after free is easy to spot
+const POISON_BYTE: u8 = 0xDE;
+
+const ALLOCATED_MAGIC: usize = 0xA110_CA7E_DA11_0CA7;
+const FREED_MAGIC: usize = 0xF4EE_DF4E_EDF4_EEDF;
+
+/// Sits right before the memory handed out. The links come first, so that
+/// the magic value survives the allocators reusing the start of a freed block
+/// for their own bookkeeping, which lets us catch most double frees.
+#[repr(C)]
+struct Header {
+    prev: *mut Header,
+    next: *mut Header,
+    size: usize,
+    magic: usize,
+    red_zone: [u8; RED_ZONE_SIZE]
+}
+
+struct LiveList {
+    head: *mut Header
+}
+
+unsafe impl Send for LiveList {}
+
+static LIVE: Mutex<LiveList> = Mutex::new(LiveList { head: 0 as *mut Header });
+
+/// Offset from the start of the block to the memory handed out
+fn prefix(layout: &Layout) -> usize {
+    let align = layout.align();
+    (mem::size_of::<Header>() + align - 1) / align * align
+}
+
+/// The layout of the block backing `layout`, with room for the header and red zones
+pub fn block_layout(layout: &Layout) -> Layout {
+    let align = cmp::max(layout.align(), mem::align_of::<Header>());
+    let size = prefix(layout) + layout.size() + RED_ZONE_SIZE;
+
+    unsafe { Layout::from_size_align_unchecked(size, align) }
+}
+
+/// Sets up the header and red zones of a fresh block and returns the memory to hand out.
+pub unsafe fn insert(block: *mut u8, layout: &Layout) -> *mut u8 {
+    let ptr = block.offset(prefix(layout) as isize);
+    let header = header(ptr);
+    let mut live = LIVE.lock();
+
+    ptr::write(header, Header {
+        prev: ptr::null_mut(),
+        next: live.head,
+        size: layout.size(),
+        magic: ALLOCATED_MAGIC,
+        red_zone: [RED_ZONE_BYTE; RED_ZONE_SIZE]
+    });
+    ptr::write_bytes(ptr.offset(layout.size() as isize), RED_ZONE_BYTE, RED_ZONE_SIZE);
+
+    if !live.head.is_null() {
+        (*live.head).prev = header;
+    }
+    live.head = header;
+
+    ptr
+}
+
+/// Checks that `ptr` is a live allocation with intact red zones, poisons it
+/// and returns the block to give back to the allocator.
+pub unsafe fn remove(ptr: *mut u8, layout: &Layout) -> *mut u8 {
+    let header = header(ptr);
+
+    match (*header).magic {
+        ALLOCATED_MAGIC => {},
+        FREED_MAGIC => panic!("Double free of {:p} ({} bytes)", ptr, layout.size()),
+        _ => panic!("Heap corruption: header of {:p} was overwritten", ptr)
+    }
+
+    if (*header).size != layout.size() {
+        panic!("Freeing {:p} with size {}, but it was allocated with size {}",
+               ptr, layout.size(), (*header).size);
+    }
+
+    if (*header).red_zone.iter().any(|&byte| byte != RED_ZONE_BYTE) {
+        panic!("Heap underrun before {:p} ({} bytes)", ptr, layout.size());
+    }
+
+    let tail = ptr.offset(layout.size() as isize);
+    for offset in 0..RED_ZONE_SIZE {
+        if *tail.offset(offset as isize) != RED_ZONE_BYTE {
+            panic!("Heap overrun after {:p} ({} bytes)", ptr, layout.size());
+        }
+    }
+
+    {
+        let mut live = LIVE.lock();
+
+        if (*header).prev.is_null() {
+            live.head = (*header).next;
+        } else {
+            (*(*header).prev).next = (*header).next;
+        }
+        if !(*header).next.is_null() {
+            (*(*header).next).prev = (*header).prev;
+        }
+    }
+
+    (*header).magic = FREED_MAGIC;
+    ptr::write_bytes(ptr, POISON_BYTE, layout.size() + RED_ZONE_SIZE);
+
+    ptr.offset(-(prefix(layout) as isize))
+}
+
+/// Calls `f` with the address and size of every live allocation, newest first.
+pub fn for_each<F: FnMut(usize, usize)>(mut f: F) {
+    let live = LIVE.lock();
+    let mut header = live.head;
+
+    while !header.is_null() {
+        unsafe {
+            f(header.offset(1) as usize, (*header).size);
+            header = (*header).next;
+        }
+    }
+}
+
+unsafe fn header(ptr: *mut u8) -> *mut Header {
+    (ptr as *mut Header).offset(-1)
+}
@@ -21,13 +21,151 @@ extern crate linked_list_allocator;
 use alloc::heap::{Alloc, AllocErr, Layout};
 use spin::Mutex;
 use linked_list_allocator::Heap;
+use slab::{SlabCache, SIZE_CLASSES};
+use stats::Tracker;
 
+pub use slab::{CacheStats, CACHE_COUNT, SLAB_SIZE};
+pub use stats::{HeapStats, SizeGroup, SIZE_GROUPS};
 
-static HEAP: Mutex<Option<Heap>> = Mutex::new(None);
+mod slab;
+mod stats;
+#[cfg(feature = "debug")]
+mod debug;
+
+/// Called with the heap locked when an allocation doesn't fit. It should make
+/// at least `size` more bytes usable right after `top`, the current end of the
+/// heap, and return how many bytes it added (0 if the heap can't grow).
+pub type GrowHandler = fn(top: usize, size: usize) -> usize;
+
+struct GrowableHeap {
+    heap: Heap,
+    grow: GrowHandler
+}
+
+impl GrowableHeap {
+    unsafe fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        match self.heap.allocate_first_fit(layout.clone()) {
+            Ok(ptr) => Ok(ptr),
+            Err(err) => {
+                // Leave room for aligning the allocation inside the new memory
+                let added = (self.grow)(self.heap.top(), layout.size() + layout.align());
+
+                if added == 0 {
+                    return Err(err);
+                }
+
+                self.heap.extend(added);
+                self.heap.allocate_first_fit(layout)
+            }
+        }
+    }
+}
+
+/// Small allocations are served by the slab caches, everything else by the
+/// linked list heap the slabs are carved from
+struct KernelHeap {
+    heap: GrowableHeap,
+    caches: [SlabCache; CACHE_COUNT],
+    tracker: Tracker
+}
+
+impl KernelHeap {
+    unsafe fn allocate_block(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        match slab::cache_index(&layout) {
+            Some(index) => self.caches[index].allocate(&mut self.heap),
+            None => self.heap.allocate(layout)
+        }
+    }
+
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
+        match slab::cache_index(&layout) {
+            Some(index) => self.caches[index].deallocate(ptr),
+            None => self.heap.heap.deallocate(ptr, layout)
+        }
+    }
+
+    #[cfg(not(feature = "debug"))]
+    unsafe fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let size = layout.size();
+        let ptr = self.allocate_block(layout)?;
+
+        self.tracker.record_alloc(size);
+        Ok(ptr)
+    }
+
+    #[cfg(feature = "debug")]
+    unsafe fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let block = self.allocate_block(debug::block_layout(&layout))?;
+
+        self.tracker.record_alloc(layout.size());
+        Ok(debug::insert(block, &layout))
+    }
+
+    #[cfg(not(feature = "debug"))]
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.tracker.record_dealloc(layout.size());
+        self.deallocate_block(ptr, layout);
+    }
+
+    #[cfg(feature = "debug")]
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let block = debug::remove(ptr, &layout);
+
+        self.tracker.record_dealloc(layout.size());
+        self.deallocate_block(block, debug::block_layout(&layout));
+    }
+}
+
+static HEAP: Mutex<Option<KernelHeap>> = Mutex::new(None);
 
 //Set up the heap
-pub unsafe fn init(offset: usize, size: usize) {
-    *HEAP.lock() = Some(Heap::new(offset, size));
+pub unsafe fn init(offset: usize, size: usize, grow: GrowHandler) {
+    let caches = [
+        SlabCache::new(SIZE_CLASSES[0]), SlabCache::new(SIZE_CLASSES[1]),
+        SlabCache::new(SIZE_CLASSES[2]), SlabCache::new(SIZE_CLASSES[3]),
+        SlabCache::new(SIZE_CLASSES[4]), SlabCache::new(SIZE_CLASSES[5]),
+        SlabCache::new(SIZE_CLASSES[6]), SlabCache::new(SIZE_CLASSES[7])
+    ];
+
+    *HEAP.lock() = Some(KernelHeap {
+        heap: GrowableHeap { heap: Heap::new(offset, size), grow: grow },
+        caches: caches,
+        tracker: Tracker::new()
+    });
+}
+
+/// Returns the usage of every slab cache, from the smallest size class up.
+pub fn cache_stats() -> [CacheStats; CACHE_COUNT] {
+    let mut stats = [CacheStats { object_size: 0, slabs: 0, allocated: 0, free: 0 }; CACHE_COUNT];
+
+    if let Some(ref heap) = *HEAP.lock() {
+        for (stat, cache) in stats.iter_mut().zip(heap.caches.iter()) {
+            *stat = cache.stats();
+        }
+    }
+
+    stats
+}
+
+/// Returns the heap counters, or `None` if the heap isn't initialized yet.
+pub fn heap_stats() -> Option<HeapStats> {
+    HEAP.lock().as_ref().map(|heap| heap.tracker.stats())
+}
+
+/// Returns the outstanding allocations grouped by size, from the smallest group up.
+pub fn outstanding_allocations() -> [SizeGroup; SIZE_GROUPS] {
+    match *HEAP.lock() {
+        Some(ref heap) => heap.tracker.size_groups(),
+        None => Tracker::new().size_groups()
+    }
+}
+
+/// Calls `f` with the address and size of every live allocation, newest
+/// first. The heap stays locked meanwhile, so `f` must not allocate.
+#[cfg(feature = "debug")]
+pub fn for_each_allocation<F: FnMut(usize, usize)>(f: F) {
+    let _heap = HEAP.lock();
+    debug::for_each(f);
 }
 
 pub struct Allocator;
@@ -35,7 +173,7 @@ pub struct Allocator;
 unsafe impl<'a> Alloc for &'a Allocator {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
         if let Some(ref mut heap) = *HEAP.lock() {
-            heap.allocate_first_fit(layout)
+            heap.allocate(layout)
         } else {
             panic!("Heap not initialized!");
         }
@@ -48,6 +186,10 @@ unsafe impl<'a> Alloc for &'a Allocator {
             panic!("heap not initalized");
         }
     }
+
+    fn oom(&mut self, err: AllocErr) -> ! {
+        panic!("Out of kernel heap memory: {:?}", err);
+    }
 }
 
 //Our allocator static
@@ -0,0 +1,105 @@
+use alloc::heap::{AllocErr, Layout};
+use core::ptr;
+use GrowableHeap;
+
+/// Each slab is carved out of the heap as one block of this size
+pub const SLAB_SIZE: usize = 4096;
+
+/// Object sizes served by the slab caches. Anything bigger goes to the heap.
+pub const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const CACHE_COUNT: usize = 8;
+
+/// Usage of a single slab cache
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub object_size: usize,
+    /// Number of slabs taken from the heap
+    pub slabs: usize,
+    /// Objects currently handed out
+    pub allocated: usize,
+    /// Objects sitting in the free list
+    pub free: usize
+}
+
+/// Free objects are linked through their first word
+struct FreeObject {
+    next: *mut FreeObject
+}
+
+pub struct SlabCache {
+    object_size: usize,
+    free_list: *mut FreeObject,
+    slabs: usize,
+    allocated: usize,
+    free: usize
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    pub const fn new(object_size: usize) -> SlabCache {
+        SlabCache {
+            object_size: object_size,
+            free_list: 0 as *mut FreeObject,
+            slabs: 0,
+            allocated: 0,
+            free: 0
+        }
+    }
+
+    /// Pops an object off the free list, taking a new slab from the heap if
+    /// the list is empty. Objects are aligned to their size, since slabs are
+    /// aligned to `SLAB_SIZE`.
+    pub unsafe fn allocate(&mut self, heap: &mut GrowableHeap) -> Result<*mut u8, AllocErr> {
+        if self.free_list.is_null() {
+            self.refill(heap)?;
+        }
+
+        let object = self.free_list;
+        self.free_list = (*object).next;
+        self.free -= 1;
+        self.allocated += 1;
+
+        Ok(object as *mut u8)
+    }
+
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8) {
+        let object = ptr as *mut FreeObject;
+        ptr::write(object, FreeObject { next: self.free_list });
+        self.free_list = object;
+        self.free += 1;
+        self.allocated -= 1;
+    }
+
+    /// Slabs are never handed back to the heap, they just stay in the free list.
+    unsafe fn refill(&mut self, heap: &mut GrowableHeap) -> Result<(), AllocErr> {
+        let slab = heap.allocate(Layout::from_size_align_unchecked(SLAB_SIZE, SLAB_SIZE))?;
+
+        for index in (0..SLAB_SIZE / self.object_size).rev() {
+            let object = slab.offset((index * self.object_size) as isize) as *mut FreeObject;
+            ptr::write(object, FreeObject { next: self.free_list });
+            self.free_list = object;
+            self.free += 1;
+        }
+
+        self.slabs += 1;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            object_size: self.object_size,
+            slabs: self.slabs,
+            allocated: self.allocated,
+            free: self.free
+        }
+    }
+}
+
+/// Returns the index of the smallest cache that can hold `layout`, if any.
+pub fn cache_index(layout: &Layout) -> Option<usize> {
+    // Objects are aligned to their size, so a big alignment needs a big class
+    let size = if layout.size() > layout.align() { layout.size() } else { layout.align() };
+
+    SIZE_CLASSES.iter().position(|&class| size <= class)
+}
@@ -0,0 +1,104 @@
+/// Number of size groups outstanding allocations are counted in. Group `n`
+/// holds allocations of up to `16 << n` bytes, the last one everything bigger.
+pub const SIZE_GROUPS: usize = 20;
+
+const SMALLEST_GROUP_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes handed out and not yet freed, as requested by the callers
+    pub bytes_in_use: usize,
+    /// The highest `bytes_in_use` has ever been
+    pub peak_bytes_in_use: usize,
+    /// Allocations made since boot
+    pub allocations: usize,
+    /// Allocations freed since boot
+    pub deallocations: usize
+}
+
+impl HeapStats {
+    /// Allocations that haven't been freed yet
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+/// Outstanding allocations of up to `max_size` bytes
+#[derive(Debug, Clone, Copy)]
+pub struct SizeGroup {
+    pub max_size: usize,
+    pub count: usize,
+    pub bytes: usize
+}
+
+pub struct Tracker {
+    stats: HeapStats,
+    counts: [usize; SIZE_GROUPS],
+    bytes: [usize; SIZE_GROUPS]
+}
+
+impl Tracker {
+    pub const fn new() -> Tracker {
+        Tracker {
+            stats: HeapStats {
+                bytes_in_use: 0,
+                peak_bytes_in_use: 0,
+                allocations: 0,
+                deallocations: 0
+            },
+            counts: [0; SIZE_GROUPS],
+            bytes: [0; SIZE_GROUPS]
+        }
+    }
+
+    pub fn record_alloc(&mut self, size: usize) {
+        self.stats.allocations += 1;
+        self.stats.bytes_in_use += size;
+        if self.stats.bytes_in_use > self.stats.peak_bytes_in_use {
+            self.stats.peak_bytes_in_use = self.stats.bytes_in_use;
+        }
+
+        let group = group_index(size);
+        self.counts[group] += 1;
+        self.bytes[group] += size;
+    }
+
+    pub fn record_dealloc(&mut self, size: usize) {
+        self.stats.deallocations += 1;
+        self.stats.bytes_in_use -= size;
+
+        let group = group_index(size);
+        self.counts[group] -= 1;
+        self.bytes[group] -= size;
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    pub fn size_groups(&self) -> [SizeGroup; SIZE_GROUPS] {
+        let mut groups = [SizeGroup { max_size: 0, count: 0, bytes: 0 }; SIZE_GROUPS];
+
+        for (index, group) in groups.iter_mut().enumerate() {
+            group.max_size = if index == SIZE_GROUPS - 1 {
+                !0
+            } else {
+                SMALLEST_GROUP_SIZE << index
+            };
+            group.count = self.counts[index];
+            group.bytes = self.bytes[index];
+        }
+
+        groups
+    }
+}
+
+fn group_index(size: usize) -> usize {
+    let mut index = 0;
+
+    while index < SIZE_GROUPS - 1 && size > SMALLEST_GROUP_SIZE << index {
+        index += 1;
+    }
+
+    index
+}
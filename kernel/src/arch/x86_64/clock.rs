@@ -1,18 +1,39 @@
-use arch::{cmos, pit};
-use spin::Mutex;
+use arch::{cmos, cpuid, pit};
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use spin::Once;
+use tasking::sync::IrqMutex;
 
-static CURRENT_SECONDS: Mutex<u64> = Mutex::new(0);
+/// Frequency of the timer interrupt, in Hz
+pub const TICK_FREQUENCY: u32 = 1000;
+
+static CURRENT_SECONDS: IrqMutex<u64> = IrqMutex::new(0);
+
+/// Ticks since `CURRENT_SECONDS` was last incremented
+static SUBSECOND_TICKS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Timer ticks the TSC is calibrated over
+const CALIBRATION_TICKS: usize = 50;
+
+/// The TSC value at boot, and how many TSC cycles make a millisecond. Only
+/// set if the TSC runs at a constant rate.
+static TSC: Once<(u64, u64)> = Once::new();
 
 pub fn init() {
     assert_has_not_been_called!("clock::init must be called only once");
 
-    pit::init();
-
     let now = cmos::current_datetime();
 
-    {
-        let mut seconds = CURRENT_SECONDS.lock();
-        *seconds = now.seconds_since_epoch();
+    *CURRENT_SECONDS.lock() = now.seconds_since_epoch();
+
+    pit::init(TICK_FREQUENCY);
+
+    if cpuid::has_invariant_tsc() {
+        let (start, cycles_per_milli) = calibrate_tsc();
+        TSC.call_once(|| (start, cycles_per_milli));
+
+        ok!("Monotonic clock uses the TSC at {} MHz.", cycles_per_milli / 1000);
+    } else {
+        ok!("Monotonic clock uses the PIT.");
     }
 
     if current_seconds() > 0 {
@@ -22,13 +43,67 @@ pub fn init() {
     }
 }
 
-// TODO: Hook up to our timer
-// pub fn tick() {
-//     let mut seconds = CURRENT_SECONDS.lock();
-//     *seconds += 1;
-// }
+/// Called from the timer interrupt on every tick
+pub fn tick() {
+    let ticks = SUBSECOND_TICKS.fetch_add(1, Ordering::SeqCst) + 1;
+
+    if ticks >= pit::frequency() as usize {
+        SUBSECOND_TICKS.store(0, Ordering::SeqCst);
+
+        let mut seconds = CURRENT_SECONDS.lock();
+        *seconds += 1;
+    }
+}
 
 pub fn current_seconds() -> u64 {
     let seconds = CURRENT_SECONDS.lock();
     *seconds
 }
+
+/// Nanoseconds since boot. Unlike the wall clock, this never jumps and never
+/// goes backwards.
+pub fn monotonic_nanos() -> u64 {
+    match TSC.try() {
+        Some(&(start, cycles_per_milli)) => {
+            let cycles = rdtsc() - start;
+
+            // Split up so that the multiplication can't overflow
+            cycles / cycles_per_milli * 1_000_000 +
+                cycles % cycles_per_milli * 1_000_000 / cycles_per_milli
+        }
+        None => {
+            let uptime = pit::ticks_to_duration(pit::ticks());
+            uptime.as_secs() * 1_000_000_000 + uptime.subsec_nanos() as u64
+        }
+    }
+}
+
+/// Measures the TSC frequency against the PIT. Returns the TSC value at the
+/// start and the cycles per millisecond.
+fn calibrate_tsc() -> (u64, u64) {
+    // Start right at a tick, so we measure whole ticks
+    let first = pit::ticks();
+    while pit::ticks() == first {}
+
+    let start_tick = pit::ticks();
+    let start = rdtsc();
+
+    while pit::ticks() < start_tick + CALIBRATION_TICKS {}
+
+    let cycles = rdtsc() - start;
+    let elapsed = pit::ticks_to_duration(CALIBRATION_TICKS);
+    let millis = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000;
+
+    // Count from when the PIT started, like the fallback does
+    let boot = start.saturating_sub(cycles / CALIBRATION_TICKS as u64 * start_tick as u64);
+
+    (boot, cycles / millis)
+}
+
+fn rdtsc() -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!("rdtsc" : "={eax}"(low), "={edx}"(high) : : : "volatile");
+    }
+    (high as u64) << 32 | low as u64
+}
@@ -0,0 +1,31 @@
+#[derive(Debug, Clone, Copy)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32
+}
+
+pub fn cpuid(leaf: u32) -> CpuidResult {
+    let (eax, ebx, ecx, edx): (u32, u32, u32, u32);
+    unsafe {
+        asm!("cpuid" : "={eax}"(eax), "={ebx}"(ebx), "={ecx}"(ecx), "={edx}"(edx)
+                     : "{eax}"(leaf), "{ecx}"(0) : : "volatile");
+    }
+    CpuidResult { eax: eax, ebx: ebx, ecx: ecx, edx: edx }
+}
+
+fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000).eax
+}
+
+/// Whether 1 GiB pages can be mapped from P3 entries
+pub fn has_1gib_pages() -> bool {
+    max_extended_leaf() >= 0x8000_0001 && cpuid(0x8000_0001).edx & (1 << 26) != 0
+}
+
+/// Whether the time stamp counter runs at a constant rate, regardless of
+/// power states and frequency changes
+pub fn has_invariant_tsc() -> bool {
+    max_extended_leaf() >= 0x8000_0007 && cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
@@ -0,0 +1,157 @@
+//! Saving and restoring the x87/SSE (and AVX, where available) registers. The
+//! state is switched eagerly on every task switch, using XSAVE if the CPU
+//! supports it and FXSAVE otherwise.
+
+use alloc::heap::{Alloc, Heap, Layout};
+use arch::cpuid::cpuid;
+use core::ptr;
+use spin::Once;
+
+/// Size of the legacy FXSAVE area
+const FXSAVE_SIZE: usize = 512;
+const FXSAVE_ALIGN: usize = 16;
+const XSAVE_ALIGN: usize = 64;
+
+/// x87 control word after FNINIT: all exceptions masked, extended precision
+const DEFAULT_FCW: u16 = 0x037F;
+/// MXCSR after reset: all exceptions masked, round to nearest
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+const FCW_OFFSET: isize = 0;
+const MXCSR_OFFSET: isize = 24;
+
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+
+#[derive(Debug, Clone, Copy)]
+enum SaveMethod {
+    Fxsave,
+    /// `mask` is the set of state components enabled in XCR0
+    Xsave { size: usize, mask: u64 }
+}
+
+static METHOD: Once<SaveMethod> = Once::new();
+
+/// Enables the FPU and SSE, and XSAVE if the CPU supports it.
+pub fn init() {
+    use x86::shared::control_regs::{cr0, cr0_write, cr4, cr4_write, CR0_EMULATE_COPROCESSOR,
+                                    CR0_MONITOR_COPROCESSOR, CR0_TASK_SWITCHED,
+                                    CR0_NUMERIC_ERROR, CR4_ENABLE_SSE, CR4_UNMASKED_SSE,
+                                    CR4_ENABLE_OS_XSAVE};
+
+    assert_has_not_been_called!("fpu::init must be called only once");
+
+    let features = cpuid(1);
+    assert!(features.edx & (1 << 25) != 0, "SSE is not supported by this CPU!");
+    let has_xsave = features.ecx & (1 << 26) != 0;
+    let has_avx = features.ecx & (1 << 28) != 0;
+
+    unsafe {
+        cr0_write((cr0() | CR0_MONITOR_COPROCESSOR | CR0_NUMERIC_ERROR) -
+                  CR0_EMULATE_COPROCESSOR - CR0_TASK_SWITCHED);
+
+        let mut flags = cr4() | CR4_ENABLE_SSE | CR4_UNMASKED_SSE;
+        if has_xsave {
+            flags = flags | CR4_ENABLE_OS_XSAVE;
+        }
+        cr4_write(flags);
+
+        asm!("fninit" : : : "memory" : "intel", "volatile");
+    }
+
+    let method = if has_xsave {
+        let mask = if has_avx { XCR0_X87 | XCR0_SSE | XCR0_AVX } else { XCR0_X87 | XCR0_SSE };
+        unsafe { xsetbv(0, mask) };
+
+        // EBX of leaf 0xD is the save area size for what is enabled in XCR0
+        SaveMethod::Xsave { size: cpuid(0xD).ebx as usize, mask: mask }
+    } else {
+        SaveMethod::Fxsave
+    };
+
+    METHOD.call_once(|| method);
+
+    match method {
+        SaveMethod::Fxsave => ok!("FPU and SSE enabled, using FXSAVE."),
+        SaveMethod::Xsave { size, .. } => {
+            ok!("FPU and SSE enabled, using XSAVE ({} byte save area).", size)
+        }
+    }
+}
+
+fn method() -> SaveMethod {
+    *METHOD.try().expect("FPU not initialized!")
+}
+
+unsafe fn xsetbv(register: u32, value: u64) {
+    asm!("xsetbv" : : "{ecx}"(register), "{eax}"(value as u32), "{edx}"((value >> 32) as u32)
+                  : : "intel", "volatile");
+}
+
+/// The FPU registers of a switched out task
+pub struct FpuState {
+    area: *mut u8,
+    layout: Layout
+}
+
+unsafe impl Send for FpuState {}
+unsafe impl Sync for FpuState {}
+
+impl FpuState {
+    /// Creates a save area holding the state right after reset, with all
+    /// floating point exceptions masked.
+    pub fn new() -> FpuState {
+        let layout = match method() {
+            SaveMethod::Fxsave => Layout::from_size_align(FXSAVE_SIZE, FXSAVE_ALIGN),
+            SaveMethod::Xsave { size, .. } => Layout::from_size_align(size, XSAVE_ALIGN)
+        }.expect("Invalid FPU save area layout!");
+
+        unsafe {
+            let area = Heap.alloc(layout.clone()).unwrap_or_else(|error| Heap.oom(error));
+
+            // An all zero XSAVE header marks every component as being in its
+            // initial state, except for the control registers set here
+            ptr::write_bytes(area, 0, layout.size());
+            ptr::write(area.offset(FCW_OFFSET) as *mut u16, DEFAULT_FCW);
+            ptr::write(area.offset(MXCSR_OFFSET) as *mut u32, DEFAULT_MXCSR);
+
+            FpuState { area: area, layout: layout }
+        }
+    }
+
+    /// Saves the current FPU registers. Must be called with interrupts disabled.
+    pub unsafe fn save(&mut self) {
+        match method() {
+            SaveMethod::Fxsave => {
+                asm!("fxsave64 [$0]" : : "r"(self.area) : "memory" : "intel", "volatile");
+            }
+            SaveMethod::Xsave { mask, .. } => {
+                asm!("xsave64 [$0]" : : "r"(self.area), "{eax}"(mask as u32),
+                                        "{edx}"((mask >> 32) as u32)
+                                    : "memory" : "intel", "volatile");
+            }
+        }
+    }
+
+    /// Loads the saved registers into the FPU. Must be called with interrupts
+    /// disabled.
+    pub unsafe fn restore(&self) {
+        match method() {
+            SaveMethod::Fxsave => {
+                asm!("fxrstor64 [$0]" : : "r"(self.area) : "memory" : "intel", "volatile");
+            }
+            SaveMethod::Xsave { mask, .. } => {
+                asm!("xrstor64 [$0]" : : "r"(self.area), "{eax}"(mask as u32),
+                                         "{edx}"((mask >> 32) as u32)
+                                     : "memory" : "intel", "volatile");
+            }
+        }
+    }
+}
+
+impl Drop for FpuState {
+    fn drop(&mut self) {
+        unsafe { Heap.dealloc(self.area, self.layout.clone()) };
+    }
+}
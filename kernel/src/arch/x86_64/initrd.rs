@@ -1,3 +1,4 @@
+use arch::memory::phys_to_virt;
 use multiboot2::BootInformation;
 use core::slice;
 use tar::*;
@@ -9,7 +10,7 @@ pub fn init(boot_info: &BootInformation) {
         .expect("Missing initrd from the multiboot config");
 
     let bytes = unsafe {
-        slice::from_raw_parts(initrd.start_address() as *const u8,
+        slice::from_raw_parts(phys_to_virt(initrd.start_address() as usize) as *const u8,
                               (initrd.end_address() - initrd.start_address()) as usize)
     };
 
@@ -5,11 +5,11 @@ use core::fmt;
 
 pub type HandlerFunc = extern "C" fn() -> !;
 
-pub struct Idt([Entry; 16]);
+pub struct Idt([Entry; 256]);
 
 impl Idt {
     pub fn new() -> Self {
-        Idt([Entry::missing(); 16])
+        Idt([Entry::missing(); 256])
     }
 
     pub fn set_handler(&mut self, entry: u8, handler: HandlerFunc)
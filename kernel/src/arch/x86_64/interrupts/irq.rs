@@ -0,0 +1,86 @@
+use spin::Mutex;
+use super::pic::PICS;
+use super::without_interrupts;
+use tasking::executor::Event;
+
+pub const IRQ_COUNT: usize = 16;
+
+pub type IrqHandler = fn();
+
+static HANDLERS: Mutex<[Option<IrqHandler>; IRQ_COUNT]> = Mutex::new([None; IRQ_COUNT]);
+
+/// Signalled on every interrupt of their line, so futures can wait for
+/// interrupts
+static EVENTS: [Event; IRQ_COUNT] = [
+    Event::new(), Event::new(), Event::new(), Event::new(),
+    Event::new(), Event::new(), Event::new(), Event::new(),
+    Event::new(), Event::new(), Event::new(), Event::new(),
+    Event::new(), Event::new(), Event::new(), Event::new()
+];
+
+/// Installs `handler` for the given legacy IRQ line and unmasks the line.
+/// Only one handler can be registered per line.
+pub fn register_irq_handler(irq: u8, handler: IrqHandler) -> Result<(), &'static str> {
+    assert!((irq as usize) < IRQ_COUNT, "Invalid IRQ: {}", irq);
+
+    // The handler table is also locked from interrupt context, so make sure we
+    // can't be interrupted while holding it.
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+
+        if handlers[irq as usize].is_some() {
+            return Err("A handler is already registered for this IRQ!");
+        }
+
+        handlers[irq as usize] = Some(handler);
+        PICS.lock().unmask(irq);
+
+        Ok(())
+    })
+}
+
+/// Masks the given IRQ line and removes its handler.
+pub fn unregister_irq_handler(irq: u8) {
+    assert!((irq as usize) < IRQ_COUNT, "Invalid IRQ: {}", irq);
+
+    without_interrupts(|| {
+        PICS.lock().mask(irq);
+        HANDLERS.lock()[irq as usize] = None;
+    })
+}
+
+/// The event signalled on every interrupt of the given line. The line has to
+/// be unmasked by registering a handler for it.
+pub fn irq_event(irq: u8) -> &'static Event {
+    assert!((irq as usize) < IRQ_COUNT, "Invalid IRQ: {}", irq);
+
+    &EVENTS[irq as usize]
+}
+
+/// Called by the IDT stubs for every hardware interrupt.
+pub fn dispatch(irq: u8) {
+    {
+        let mut pics = PICS.lock();
+
+        if pics.is_spurious(irq) {
+            return;
+        }
+
+        // Interrupts stay disabled until the `iretq`, so it is safe to
+        // acknowledge the IRQ before running the handler. This way a handler
+        // that never returns to us (e.g. because it switches tasks) doesn't
+        // leave the line blocked.
+        pics.end_of_interrupt(irq);
+    }
+
+    let handler = HANDLERS.lock()[irq as usize];
+
+    // Futures only get polled once we are back in task context, after the
+    // handler is done. Signalling first means a handler that switches tasks
+    // doesn't delay the wakeup.
+    EVENTS[irq as usize].signal();
+
+    if let Some(handler) = handler {
+        handler();
+    }
+}
@@ -1,10 +1,18 @@
 use spin::Once;
-use arch::memory::MemoryController;
+use arch::memory;
 use x86::bits64::task::TaskStateSegment;
 
+pub use self::irq::{IrqHandler, irq_event, register_irq_handler, unregister_irq_handler};
+
 mod idt;
 mod gdt;
+mod irq;
+mod pic;
+
 const DOUBLE_FAULT_IST_INDEX: usize = 0;
+/// Page faults get their own stack, so that a task overflowing into its
+/// guard page can still be reported instead of causing a double fault
+const PAGE_FAULT_IST_INDEX: usize = 1;
 
 macro_rules! save_scratch_registers {
     () => {
@@ -80,6 +88,17 @@ macro_rules! handler_with_error_code {
         wrapper
     }}
 }
+
+macro_rules! irq_handlers {
+    ($($name: ident => $irq: expr),*) => {
+        $(
+            extern "C" fn $name(_stack_frame: &ExceptionStackFrame) {
+                irq::dispatch($irq);
+            }
+        )*
+    }
+}
+
 bitflags! {
     flags PageFaultErrorCode: u64 {
         const PROTECTION_VIOLATION = 1 << 0,
@@ -102,7 +121,25 @@ lazy_static! {
         idt.set_handler(6, handler!(invalid_opcode_handler));
         idt.set_handler(8, handler_with_error_code!(double_fault_handler))
                 .set_stack_index(DOUBLE_FAULT_IST_INDEX as u16);
-        idt.set_handler(14, handler_with_error_code!(page_fault_handler));
+        idt.set_handler(14, handler_with_error_code!(page_fault_handler))
+                .set_stack_index(PAGE_FAULT_IST_INDEX as u16);
+
+        idt.set_handler(pic::PIC1_OFFSET + 0, handler!(irq0_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 1, handler!(irq1_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 2, handler!(irq2_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 3, handler!(irq3_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 4, handler!(irq4_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 5, handler!(irq5_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 6, handler!(irq6_handler));
+        idt.set_handler(pic::PIC1_OFFSET + 7, handler!(irq7_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 0, handler!(irq8_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 1, handler!(irq9_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 2, handler!(irq10_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 3, handler!(irq11_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 4, handler!(irq12_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 5, handler!(irq13_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 6, handler!(irq14_handler));
+        idt.set_handler(pic::PIC2_OFFSET + 7, handler!(irq15_handler));
 
         idt
     };
@@ -118,16 +155,19 @@ struct ExceptionStackFrame {
     stack_segment: u64,
 }
 
-pub fn init(memory_controller: &mut MemoryController) {
+pub fn init() {
     use x86::shared::segmentation::{SegmentSelector, set_cs};
     use x86::shared::task::load_tr;
 
-    let double_fault_stack = memory_controller.alloc_stack(1)
+    let double_fault_stack = memory::controller().alloc_stack(1)
         .expect("Unable to allocate double fault stack!");
+    let page_fault_stack = memory::controller().alloc_stack(2)
+        .expect("Unable to allocate page fault stack!");
 
     let tss = TSS.call_once(|| {
         let mut tss = TaskStateSegment::new();
         tss.ist[DOUBLE_FAULT_IST_INDEX] = double_fault_stack.top() as u64;
+        tss.ist[PAGE_FAULT_IST_INDEX] = page_fault_stack.top() as u64;
         tss
     });
 
@@ -149,9 +189,55 @@ pub fn init(memory_controller: &mut MemoryController) {
 
     IDT.load();
 
+    pic::PICS.lock().init();
+
+    unsafe { ::x86::shared::irq::enable() };
+
     ok!("Interrupts initialized");
 }
 
+/// Runs `f` with interrupts disabled, restoring the previous interrupt state
+/// afterwards. Use this around locks that are also taken from IRQ handlers.
+pub fn without_interrupts<F, R>(f: F) -> R where F: FnOnce() -> R {
+    use x86::shared::flags::{flags, FLAGS_IF};
+    use x86::shared::irq;
+
+    let enabled = flags().contains(FLAGS_IF);
+
+    if enabled {
+        unsafe { irq::disable() };
+    }
+
+    let result = f();
+
+    if enabled {
+        unsafe { irq::enable() };
+    }
+
+    result
+}
+
+/// Enables interrupts and waits for the next one. `sti` only takes effect
+/// after the following instruction, so an interrupt can't arrive in between
+/// and leave the CPU halted.
+pub fn enable_and_halt() {
+    unsafe { asm!("sti; hlt" : : : "memory" : "volatile") };
+}
+
+/// Stops the CPU for good. Non-maskable interrupts still wake it up, so halt
+/// again after those.
+pub fn halt_forever() -> ! {
+    loop {
+        unsafe { asm!("cli; hlt" : : : "memory" : "volatile") };
+    }
+}
+
+irq_handlers!(irq0_handler => 0, irq1_handler => 1, irq2_handler => 2, irq3_handler => 3,
+              irq4_handler => 4, irq5_handler => 5, irq6_handler => 6, irq7_handler => 7,
+              irq8_handler => 8, irq9_handler => 9, irq10_handler => 10, irq11_handler => 11,
+              irq12_handler => 12, irq13_handler => 13, irq14_handler => 14,
+              irq15_handler => 15);
+
 extern "C" fn divide_by_zero_handler(stack_frame: &ExceptionStackFrame) {
     fail!("\nEXCEPTION: DIVIDE BY ZERO\n\n{:#?}",
              stack_frame);
@@ -178,11 +264,22 @@ extern "C" fn double_fault_handler(stack_frame: &ExceptionStackFrame,
 
 extern "C" fn page_fault_handler(stack_frame: &ExceptionStackFrame, error_code: u64) {
     use x86::shared::control_regs;
+
+    let address = unsafe { control_regs::cr2() };
+
+    if let Some(id) = ::tasking::stack_overflow_task(address) {
+        fail!("\nEXCEPTION: STACK OVERFLOW in task {:?} (guard page hit at {:#x})\
+               \n\
+               \n{:#?}",
+                id, address, stack_frame);
+        loop {}
+    }
+
     fail!("\nEXCEPTION: PAGE FAULT while accessing {:#x}\
            \n    Error code: {:?}\
            \n\
            \n{:#?}",
-            unsafe { control_regs::cr2() }, PageFaultErrorCode::from_bits(error_code).unwrap(),
+            address, PageFaultErrorCode::from_bits(error_code).unwrap(),
             stack_frame);
     loop {}
 }
@@ -0,0 +1,145 @@
+use arch::io::Port;
+use spin::Mutex;
+
+/// Vector of IRQ 0 after remapping, right after the 32 CPU exceptions
+pub const PIC1_OFFSET: u8 = 32;
+/// Vector of IRQ 8 after remapping
+pub const PIC2_OFFSET: u8 = PIC1_OFFSET + 8;
+
+/// The slave PIC is wired to this line of the master PIC
+const CASCADE_IRQ: u8 = 2;
+
+const CMD_INIT: u8 = 0x11;
+const CMD_END_OF_INTERRUPT: u8 = 0x20;
+const CMD_READ_ISR: u8 = 0x0B;
+const MODE_8086: u8 = 0x01;
+
+pub static PICS: Mutex<ChainedPics> = Mutex::new(unsafe {
+    ChainedPics::new(PIC1_OFFSET, PIC2_OFFSET)
+});
+
+struct Pic {
+    offset: u8,
+    command: Port<u8>,
+    data: Port<u8>
+}
+
+impl Pic {
+    fn end_of_interrupt(&mut self) {
+        self.command.write(CMD_END_OF_INTERRUPT);
+    }
+
+    fn in_service(&mut self) -> u8 {
+        self.command.write(CMD_READ_ISR);
+        self.command.read()
+    }
+}
+
+/// The master and slave 8259 PICs of a legacy PC, with the slave cascaded
+/// through IRQ 2 of the master.
+pub struct ChainedPics {
+    pics: [Pic; 2],
+    // Port 0x80 is unused, so writing to it is a cheap way of giving the
+    // PICs time to react between initialization words.
+    wait_port: Port<u8>
+}
+
+impl ChainedPics {
+    pub const unsafe fn new(offset1: u8, offset2: u8) -> ChainedPics {
+        ChainedPics {
+            pics: [
+                Pic { offset: offset1, command: Port::new(0x20), data: Port::new(0x21) },
+                Pic { offset: offset2, command: Port::new(0xA0), data: Port::new(0xA1) }
+            ],
+            wait_port: Port::new(0x80)
+        }
+    }
+
+    /// Remaps both PICs to their offsets and masks every IRQ line except the
+    /// cascade, so that no hardware interrupt is delivered until a handler is
+    /// registered for it.
+    pub fn init(&mut self) {
+        // ICW1: start the initialization sequence (in cascade mode, expecting ICW4)
+        self.pics[0].command.write(CMD_INIT);
+        self.wait();
+        self.pics[1].command.write(CMD_INIT);
+        self.wait();
+
+        // ICW2: vector offsets
+        let (offset1, offset2) = (self.pics[0].offset, self.pics[1].offset);
+        self.pics[0].data.write(offset1);
+        self.wait();
+        self.pics[1].data.write(offset2);
+        self.wait();
+
+        // ICW3: tell the master there is a slave at IRQ 2 (0000 0100) and
+        // tell the slave its cascade identity (0000 0010)
+        self.pics[0].data.write(1 << CASCADE_IRQ);
+        self.wait();
+        self.pics[1].data.write(CASCADE_IRQ);
+        self.wait();
+
+        // ICW4: 8086 mode
+        self.pics[0].data.write(MODE_8086);
+        self.wait();
+        self.pics[1].data.write(MODE_8086);
+        self.wait();
+
+        self.pics[0].data.write(!(1 << CASCADE_IRQ));
+        self.pics[1].data.write(0xFF);
+    }
+
+    fn wait(&mut self) {
+        self.wait_port.write(0);
+    }
+
+    pub fn mask(&mut self, irq: u8) {
+        let (pic, line) = self.line(irq);
+        let mask = pic.data.read() | (1 << line);
+        pic.data.write(mask);
+    }
+
+    pub fn unmask(&mut self, irq: u8) {
+        let (pic, line) = self.line(irq);
+        let mask = pic.data.read() & !(1 << line);
+        pic.data.write(mask);
+    }
+
+    /// Spurious IRQs can be raised on the lowest priority line of either PIC
+    /// (IRQ 7 and IRQ 15). In that case the in-service bit is not set and no
+    /// EOI must be sent for that line (though the master still expects one
+    /// for the cascade if the spurious IRQ came from the slave).
+    pub fn is_spurious(&mut self, irq: u8) -> bool {
+        if irq != 7 && irq != 15 {
+            return false;
+        }
+
+        let spurious = {
+            let (pic, line) = self.line(irq);
+            pic.in_service() & (1 << line) == 0
+        };
+
+        if spurious && irq == 15 {
+            self.pics[0].end_of_interrupt();
+        }
+
+        spurious
+    }
+
+    pub fn end_of_interrupt(&mut self, irq: u8) {
+        if irq >= 8 {
+            self.pics[1].end_of_interrupt();
+        }
+        self.pics[0].end_of_interrupt();
+    }
+
+    fn line(&mut self, irq: u8) -> (&mut Pic, u8) {
+        assert!(irq < 16, "Invalid IRQ: {}", irq);
+
+        if irq < 8 {
+            (&mut self.pics[0], irq)
+        } else {
+            (&mut self.pics[1], irq - 8)
+        }
+    }
+}
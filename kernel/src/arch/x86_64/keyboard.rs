@@ -0,0 +1,422 @@
+use arch::interrupts;
+use arch::ps2;
+use spin::Once;
+use tasking::WaitQueue;
+use tasking::executor::{Event, Future, Poll, Waker};
+use tasking::sync::IrqMutex;
+
+const KEYBOARD_IRQ: u8 = 1;
+
+/// Prefix byte for the extended (0xE0) scancodes
+const EXTENDED_PREFIX: u8 = 0xE0;
+/// Starts the sequence Pause sends: E1 1D 45 E1 9D C5
+const PAUSE_PREFIX: u8 = 0xE1;
+/// Bytes following the first `PAUSE_PREFIX` in the Pause sequence
+const PAUSE_SEQUENCE_REST: u8 = 5;
+/// Set on the scancode when a key is released
+const BREAK_BIT: u8 = 0x80;
+
+const QUEUE_SIZE: usize = 64;
+
+static KEYBOARD: IrqMutex<Keyboard> = IrqMutex::new(Keyboard::new());
+
+/// Tasks waiting for a key event
+static EVENT_WAITERS: Once<WaitQueue> = Once::new();
+/// Signalled when a key event is queued, for futures waiting for one
+static KEY_EVENT: Event = Event::new();
+
+/***** ENUMS AND STRUCTS *****/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    /// A key that produces a character, identified by its unshifted US layout character
+    Char(char),
+    Escape,
+    Backspace,
+    Tab,
+    Enter,
+    LeftControl,
+    RightControl,
+    LeftShift,
+    RightShift,
+    LeftAlt,
+    RightAlt,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    /// Function keys F1 to F12
+    Function(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// Only ever pressed, the key sends no release scancode
+    Pause,
+    /// A scancode we don't decode (yet)
+    Unknown(u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released
+}
+
+bitflags! {
+    pub flags Modifiers: u8 {
+        const LEFT_SHIFT =    1 << 0,
+        const RIGHT_SHIFT =   1 << 1,
+        const LEFT_CONTROL =  1 << 2,
+        const RIGHT_CONTROL = 1 << 3,
+        const LEFT_ALT =      1 << 4,
+        const RIGHT_ALT =     1 << 5,
+        const CAPS_LOCK =     1 << 6,
+        const NUM_LOCK =      1 << 7,
+    }
+}
+
+impl Modifiers {
+    pub fn shift(&self) -> bool {
+        self.intersects(LEFT_SHIFT | RIGHT_SHIFT)
+    }
+
+    pub fn control(&self) -> bool {
+        self.intersects(LEFT_CONTROL | RIGHT_CONTROL)
+    }
+
+    pub fn alt(&self) -> bool {
+        self.intersects(LEFT_ALT | RIGHT_ALT)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Modifiers in effect after this event was applied
+    pub modifiers: Modifiers,
+    /// The character this key press produces with the US layout, if any
+    pub character: Option<char>
+}
+
+/// A fixed size ring buffer, so that the IRQ handler never has to allocate
+struct EventQueue {
+    events: [Option<KeyEvent>; QUEUE_SIZE],
+    head: usize,
+    len: usize
+}
+
+impl EventQueue {
+    const fn new() -> EventQueue {
+        EventQueue { events: [None; QUEUE_SIZE], head: 0, len: 0 }
+    }
+
+    /// Drops the event if the queue is full
+    fn push(&mut self, event: KeyEvent) {
+        if self.len < QUEUE_SIZE {
+            self.events[(self.head + self.len) % QUEUE_SIZE] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<KeyEvent> {
+        if self.len > 0 {
+            let event = self.events[self.head].take();
+            self.head = (self.head + 1) % QUEUE_SIZE;
+            self.len -= 1;
+            event
+        } else {
+            None
+        }
+    }
+}
+
+struct Keyboard {
+    extended: bool,
+    /// Bytes of the Pause sequence still to come
+    pause_remaining: u8,
+    modifiers: Modifiers,
+    queue: EventQueue
+}
+
+impl Keyboard {
+    const fn new() -> Keyboard {
+        Keyboard {
+            extended: false,
+            pause_remaining: 0,
+            modifiers: Modifiers { bits: 0 },
+            queue: EventQueue::new()
+        }
+    }
+
+    fn handle_scancode(&mut self, scancode: u8) {
+        // The Pause sequence would otherwise decode as Control and NumLock
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+
+            if self.pause_remaining == 0 {
+                self.queue.push(KeyEvent { code: KeyCode::Pause, state: KeyState::Pressed,
+                                           modifiers: self.modifiers, character: None });
+            }
+            return;
+        }
+
+        if scancode == PAUSE_PREFIX {
+            self.pause_remaining = PAUSE_SEQUENCE_REST;
+            self.extended = false;
+            return;
+        }
+
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return;
+        }
+
+        let state = if scancode & BREAK_BIT != 0 { KeyState::Released } else { KeyState::Pressed };
+        let code = if self.extended {
+            decode_extended(scancode & !BREAK_BIT)
+        } else {
+            decode(scancode & !BREAK_BIT, self.modifiers.contains(NUM_LOCK))
+        };
+        self.extended = false;
+
+        self.update_modifiers(code, state);
+
+        let character = match state {
+            KeyState::Pressed => character(code, self.modifiers),
+            KeyState::Released => None
+        };
+
+        self.queue.push(KeyEvent { code: code, state: state, modifiers: self.modifiers,
+                                   character: character });
+    }
+
+    fn update_modifiers(&mut self, code: KeyCode, state: KeyState) {
+        let pressed = state == KeyState::Pressed;
+
+        let modifier = match code {
+            KeyCode::LeftShift => LEFT_SHIFT,
+            KeyCode::RightShift => RIGHT_SHIFT,
+            KeyCode::LeftControl => LEFT_CONTROL,
+            KeyCode::RightControl => RIGHT_CONTROL,
+            KeyCode::LeftAlt => LEFT_ALT,
+            KeyCode::RightAlt => RIGHT_ALT,
+            KeyCode::CapsLock if pressed => return self.modifiers.toggle(CAPS_LOCK),
+            KeyCode::NumLock if pressed => return self.modifiers.toggle(NUM_LOCK),
+            _ => return
+        };
+
+        if pressed {
+            self.modifiers.insert(modifier);
+        } else {
+            self.modifiers.remove(modifier);
+        }
+    }
+}
+
+/***** SCANCODE SET 1 *****/
+
+fn decode(scancode: u8, num_lock: bool) -> KeyCode {
+    // Printable keys, indexed from scancode 0x02 up to 0x35
+    const PRINTABLE: &'static [u8] = b"1234567890-=\x00\x00qwertyuiop[]\x00\x00asdfghjkl;'`\x00\\zxcvbnm,./";
+
+    match scancode {
+        0x01 => KeyCode::Escape,
+        0x0E => KeyCode::Backspace,
+        0x0F => KeyCode::Tab,
+        0x1C => KeyCode::Enter,
+        0x1D => KeyCode::LeftControl,
+        0x2A => KeyCode::LeftShift,
+        0x36 => KeyCode::RightShift,
+        0x37 => KeyCode::Char('*'),
+        0x38 => KeyCode::LeftAlt,
+        0x39 => KeyCode::Char(' '),
+        0x3A => KeyCode::CapsLock,
+        0x3B...0x44 => KeyCode::Function(scancode - 0x3B + 1),
+        0x45 => KeyCode::NumLock,
+        0x46 => KeyCode::ScrollLock,
+        // Without NumLock, the keypad doubles as the navigation block
+        0x47...0x49 | 0x4B | 0x4D | 0x4F...0x53 if !num_lock => decode_extended(scancode),
+        0x4C if !num_lock => KeyCode::Unknown(scancode),
+        0x47 => KeyCode::Char('7'),
+        0x48 => KeyCode::Char('8'),
+        0x49 => KeyCode::Char('9'),
+        0x4A => KeyCode::Char('-'),
+        0x4B => KeyCode::Char('4'),
+        0x4C => KeyCode::Char('5'),
+        0x4D => KeyCode::Char('6'),
+        0x4E => KeyCode::Char('+'),
+        0x4F => KeyCode::Char('1'),
+        0x50 => KeyCode::Char('2'),
+        0x51 => KeyCode::Char('3'),
+        0x52 => KeyCode::Char('0'),
+        0x53 => KeyCode::Char('.'),
+        0x57 => KeyCode::Function(11),
+        0x58 => KeyCode::Function(12),
+        0x02...0x35 if PRINTABLE[(scancode - 0x02) as usize] != 0 => {
+            KeyCode::Char(PRINTABLE[(scancode - 0x02) as usize] as char)
+        }
+        _ => KeyCode::Unknown(scancode)
+    }
+}
+
+fn decode_extended(scancode: u8) -> KeyCode {
+    match scancode {
+        0x1C => KeyCode::Enter,
+        0x1D => KeyCode::RightControl,
+        0x35 => KeyCode::Char('/'),
+        0x38 => KeyCode::RightAlt,
+        0x47 => KeyCode::Home,
+        0x48 => KeyCode::Up,
+        0x49 => KeyCode::PageUp,
+        0x4B => KeyCode::Left,
+        0x4D => KeyCode::Right,
+        0x4F => KeyCode::End,
+        0x50 => KeyCode::Down,
+        0x51 => KeyCode::PageDown,
+        0x52 => KeyCode::Insert,
+        0x53 => KeyCode::Delete,
+        _ => KeyCode::Unknown(scancode)
+    }
+}
+
+/// Maps a key to the character it produces on a US keyboard
+fn character(code: KeyCode, modifiers: Modifiers) -> Option<char> {
+    match code {
+        KeyCode::Char(c) if c >= 'a' && c <= 'z' => {
+            if modifiers.shift() != modifiers.contains(CAPS_LOCK) {
+                Some((c as u8 - b'a' + b'A') as char)
+            } else {
+                Some(c)
+            }
+        }
+        KeyCode::Char(c) if modifiers.shift() => Some(shifted(c)),
+        KeyCode::Char(c) => Some(c),
+        KeyCode::Enter => Some('\n'),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Backspace => Some('\x08'),
+        _ => None
+    }
+}
+
+fn shifted(c: char) -> char {
+    match c {
+        '1' => '!', '2' => '@', '3' => '#', '4' => '$', '5' => '%',
+        '6' => '^', '7' => '&', '8' => '*', '9' => '(', '0' => ')',
+        '-' => '_', '=' => '+', '[' => '{', ']' => '}', '\\' => '|',
+        ';' => ':', '\'' => '"', '`' => '~', ',' => '<', '.' => '>',
+        '/' => '?',
+        c => c
+    }
+}
+
+/***** FUNCTIONS *****/
+
+pub fn init() {
+    assert_has_not_been_called!("keyboard::init must be called only once");
+
+    EVENT_WAITERS.call_once(WaitQueue::new);
+
+    if let Err(error) = ps2::CONTROLLER.lock().init() {
+        fail!("Unable to initialize the PS/2 controller: {}", error);
+        return;
+    }
+
+    interrupts::register_irq_handler(KEYBOARD_IRQ, handle_irq)
+        .expect("Unable to register the keyboard interrupt handler!");
+
+    ok!("Keyboard initialized.");
+}
+
+fn handle_irq() {
+    let scancode = ps2::CONTROLLER.lock().read_data();
+
+    KEYBOARD.lock().handle_scancode(scancode);
+
+    if let Some(waiters) = EVENT_WAITERS.try() {
+        waiters.wake_all();
+    }
+    KEY_EVENT.signal();
+}
+
+/// Returns the next key event, if there is one.
+pub fn try_read_event() -> Option<KeyEvent> {
+    KEYBOARD.lock().queue.pop()
+}
+
+/// Waits for the next key event.
+pub fn read_event() -> KeyEvent {
+    let waiters = EVENT_WAITERS.try().expect("Keyboard not initialized!");
+    let mut event = None;
+
+    waiters.wait_until(|| {
+        event = try_read_event();
+        event.is_some()
+    });
+
+    event.expect("Woke up without a key event!")
+}
+
+/// A future resolving to the next key event
+pub struct NextEvent {
+    /// The waker we registered with `KEY_EVENT`, if any
+    registered: Option<Waker>
+}
+
+/// Returns a future resolving to the next key event, for waiting for input
+/// from an executor instead of blocking the task.
+pub fn next_event() -> NextEvent {
+    NextEvent { registered: None }
+}
+
+impl Future for NextEvent {
+    type Output = KeyEvent;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<KeyEvent> {
+        if let Some(event) = try_read_event() {
+            self.deregister();
+            return Poll::Ready(event);
+        }
+
+        KEY_EVENT.register(waker);
+        self.registered = Some(waker.clone());
+
+        // An event might have come in before the waker was registered
+        match try_read_event() {
+            Some(event) => {
+                self.deregister();
+                Poll::Ready(event)
+            }
+            None => Poll::Pending
+        }
+    }
+}
+
+impl NextEvent {
+    fn deregister(&mut self) {
+        if let Some(waker) = self.registered.take() {
+            KEY_EVENT.deregister(&waker);
+        }
+    }
+}
+
+impl Drop for NextEvent {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+/// Waits for the next key press that produces a character.
+pub fn read_char() -> char {
+    loop {
+        if let Some(character) = read_event().character {
+            return character;
+        }
+    }
+}
@@ -1,91 +1,122 @@
-use multiboot2::{MemoryAreaIter, MemoryArea};
-use super::Frame;
+use multiboot2::MemoryAreaIter;
+use super::{Frame, PhysicalAddress, PAGE_SIZE};
+use super::paging::MAX_FRAMES;
+
+const BITS_PER_WORD: usize = 64;
+const BITMAP_WORDS: usize = MAX_FRAMES / BITS_PER_WORD;
+
+/// One bit per physical frame, set if the frame is free. This lives in the
+/// kernel's .bss, so it is usable before the heap exists.
+static mut FRAME_BITMAP: [u64; BITMAP_WORDS] = [0; BITMAP_WORDS];
 
 pub trait FrameAllocator {
     fn allocate_frame(&mut self) -> Option<Frame>;
     fn deallocate_frame(&mut self, frame: Frame);
 }
 
-pub struct AreaFrameAllocator {
-    next_free_frame: Frame,
-    current_area: Option<&'static MemoryArea>,
-    areas: MemoryAreaIter,
-    kernel_start: Frame,
-    kernel_end: Frame,
-    multiboot_start: Frame,
-    multiboot_end: Frame,
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64; BITMAP_WORDS],
+    /// No word before this one has a free frame
+    next_word: usize,
+    free_frames: usize
 }
 
-impl AreaFrameAllocator {
-    pub fn new(kernel_start: usize, kernel_end: usize, multiboot_start: usize,
-               multiboot_end: usize, memory_areas: MemoryAreaIter) -> AreaFrameAllocator {
-        let mut allocator = AreaFrameAllocator {
-            next_free_frame: Frame::containing_address(0),
-            current_area: None,
-            areas: memory_areas,
-            kernel_start: Frame::containing_address(kernel_start),
-            kernel_end: Frame::containing_address(kernel_end),
-            multiboot_start: Frame::containing_address(multiboot_start),
-            multiboot_end: Frame::containing_address(multiboot_end),
+impl BitmapFrameAllocator {
+    /// Marks every frame of the available memory areas as free. Memory that
+    /// is in use (the kernel, multiboot structures, modules...) needs to be
+    /// reserved with `reserve_range` before allocating any frames.
+    pub fn new(memory_areas: MemoryAreaIter) -> BitmapFrameAllocator {
+        assert_has_not_been_called!("BitmapFrameAllocator::new must be called only once");
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap: unsafe { &mut FRAME_BITMAP },
+            next_word: 0,
+            free_frames: 0
         };
-        allocator.choose_next_area();
+
+        for area in memory_areas {
+            // Only whole frames inside the area are usable
+            let start = Frame::containing_address(area.base_addr as usize + PAGE_SIZE - 1);
+            let end = Frame::containing_address((area.base_addr + area.length) as usize);
+
+            for number in start.number..end.number {
+                if number >= MAX_FRAMES {
+                    break;
+                }
+                if !allocator.is_free(number) {
+                    allocator.mark_free(number);
+                }
+            }
+        }
+
+        // Frame 0 would be a null pointer to anyone using physical addresses
+        allocator.reserve_range(0, PAGE_SIZE);
+
         allocator
     }
 
-    fn choose_next_area(&mut self) {
-        self.current_area = self.areas.clone().filter(|area| {
-            let address = area.base_addr + area.length - 1;
-            Frame::containing_address(address as usize) >= self.next_free_frame
-        }).min_by_key(|area| area.base_addr);
+    /// Marks the frames containing the physical range `start..end` as used.
+    pub fn reserve_range(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+        if start >= end {
+            return;
+        }
+
+        let start_frame = Frame::containing_address(start);
+        let end_frame = Frame::containing_address(end - 1);
 
-        if let Some(area) = self.current_area {
-            let start_frame = Frame::containing_address(area.base_addr as usize);
-            if self.next_free_frame < start_frame {
-                self.next_free_frame = start_frame;
+        for frame in Frame::range_inclusive(start_frame, end_frame) {
+            if frame.number < MAX_FRAMES && self.is_free(frame.number) {
+                self.mark_used(frame.number);
             }
         }
     }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    fn is_free(&self, number: usize) -> bool {
+        self.bitmap[number / BITS_PER_WORD] & (1 << (number % BITS_PER_WORD)) != 0
+    }
+
+    fn mark_free(&mut self, number: usize) {
+        self.bitmap[number / BITS_PER_WORD] |= 1 << (number % BITS_PER_WORD);
+        self.free_frames += 1;
+    }
+
+    fn mark_used(&mut self, number: usize) {
+        self.bitmap[number / BITS_PER_WORD] &= !(1 << (number % BITS_PER_WORD));
+        self.free_frames -= 1;
+    }
 }
 
-impl FrameAllocator for AreaFrameAllocator {
+impl FrameAllocator for BitmapFrameAllocator {
     fn allocate_frame(&mut self) -> Option<Frame> {
-        if let Some(area) = self.current_area {
-            // "Clone" the frame to return it if it's free. Frame doesn't
-            // implement Clone, but we can construct an identical frame.
-            let frame = Frame { number: self.next_free_frame.number };
-
-            // the last frame of the current area
-            let current_area_last_frame = {
-                let address = area.base_addr + area.length - 1;
-                Frame::containing_address(address as usize)
-            };
-
-            if frame > current_area_last_frame {
-                // all frames of current area are used, switch to next area
-                self.choose_next_area();
-            } else if frame >= self.kernel_start && frame <= self.kernel_end {
-                // `frame` is used by the kernel
-                self.next_free_frame = Frame {
-                    number: self.kernel_end.number + 1
-                };
-            } else if frame >= self.multiboot_start && frame <= self.multiboot_end {
-                // `frame` is used by the multiboot information structure
-                self.next_free_frame = Frame {
-                    number: self.multiboot_end.number + 1
-                };
-            } else {
-                // frame is unused, increment `next_free_frame` and return it
-                self.next_free_frame.number += 1;
-                return Some(frame);
+        for index in self.next_word..BITMAP_WORDS {
+            let word = self.bitmap[index];
+
+            if word != 0 {
+                let number = index * BITS_PER_WORD + word.trailing_zeros() as usize;
+                self.mark_used(number);
+                self.next_word = index;
+
+                return Some(Frame { number: number });
             }
-            // `frame` was not valid, try it again with the updated `next_free_frame`
-            self.allocate_frame()
-        } else {
-            None // no free frames left
         }
+
+        self.next_word = BITMAP_WORDS;
+        None // no free frames left
     }
 
-    fn deallocate_frame(&mut self, _frame: Frame) {
-        // TODO: Implement!
+    fn deallocate_frame(&mut self, frame: Frame) {
+        assert!(frame.number < MAX_FRAMES, "Invalid frame: {:?}", frame);
+        assert!(!self.is_free(frame.number), "Frame deallocated twice: {:?}", frame);
+
+        self.mark_free(frame.number);
+
+        let index = frame.number / BITS_PER_WORD;
+        if index < self.next_word {
+            self.next_word = index;
+        }
     }
 }
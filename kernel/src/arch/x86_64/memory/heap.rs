@@ -1,20 +1,113 @@
-use super::paging::{self, ActivePageTable};
-use super::{Page, KERNEL_HEAP_START, KERNEL_HEAP_SIZE};
+use core::cmp::{max, min};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use super::paging::{self, ActivePageTable, EntryFlags};
+use super::{Page, MEMORY_CONTROLLER, KERNEL_HEAP_START, KERNEL_HEAP_INITIAL_SIZE,
+            KERNEL_HEAP_MAX_SIZE, PAGE_SIZE};
 use super::frame_allocator::FrameAllocator;
 use alloc_kernel;
 
+/// The heap grows by at least this many bytes at a time
+const HEAP_GROWTH_STEP: usize = 1024 * 1024; // 1 MB
+
+static HEAP_MAX_SIZE: AtomicUsize = AtomicUsize::new(KERNEL_HEAP_MAX_SIZE);
+
+fn heap_flags() -> EntryFlags {
+    paging::PRESENT | paging::GLOBAL | paging::WRITABLE | paging::NO_EXECUTE
+}
+
+/// Maps the initial heap and returns the last page of the region reserved
+/// for the heap to grow into.
 pub fn init<A>(active_table: &mut ActivePageTable, frame_allocator: &mut A) -> Page
         where A: FrameAllocator {
     assert_has_not_been_called!("heap::init must be called only once");
 
     let heap_start_page = Page::containing_address(KERNEL_HEAP_START);
-    let heap_end_page = Page::containing_address(KERNEL_HEAP_START + KERNEL_HEAP_SIZE - 1);
+    let heap_end_page = Page::containing_address(KERNEL_HEAP_START + KERNEL_HEAP_INITIAL_SIZE - 1);
 
     for page in Page::range_inclusive(heap_start_page, heap_end_page) {
-        active_table.map(page, paging::PRESENT | paging::GLOBAL | paging::WRITABLE | paging::NO_EXECUTE, frame_allocator);
+        active_table.map(page, heap_flags(), frame_allocator);
+    }
+
+    unsafe { alloc_kernel::init(KERNEL_HEAP_START, KERNEL_HEAP_INITIAL_SIZE, grow) };
+
+    Page::containing_address(KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE - 1)
+}
+
+/// Limits how far the heap may grow. This can't be raised above
+/// `KERNEL_HEAP_MAX_SIZE`, since the address space after that is used for stacks.
+pub fn set_max_size(size: usize) {
+    HEAP_MAX_SIZE.store(min(size, KERNEL_HEAP_MAX_SIZE), Ordering::SeqCst);
+}
+
+/// Maps more memory at the end of the heap when it runs out. This is called
+/// by the allocator with the heap locked.
+fn grow(top: usize, size: usize) -> usize {
+    let heap_end = KERNEL_HEAP_START + HEAP_MAX_SIZE.load(Ordering::SeqCst);
+    let size = (max(size, HEAP_GROWTH_STEP) + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    let end = min(top + size, heap_end);
+
+    if end <= top {
+        return 0;
+    }
+
+    // The heap can't grow before the memory manager is fully initialized
+    let mut controller = match MEMORY_CONTROLLER.try() {
+        Some(controller) => controller.lock(),
+        None => return 0
+    };
+    let controller = &mut *controller;
+
+    let start_page = Page::containing_address(top);
+    let end_page = Page::containing_address(end - 1);
+    let mut added = 0;
+
+    for page in Page::range_inclusive(start_page, end_page) {
+        match controller.frame_allocator.allocate_frame() {
+            Some(frame) => {
+                controller.active_table.map_to(page, frame, heap_flags(),
+                                               &mut controller.frame_allocator);
+                added += PAGE_SIZE;
+            }
+            None => break
+        }
     }
 
-    unsafe { alloc_kernel::init(KERNEL_HEAP_START, KERNEL_HEAP_SIZE) };
+    added
+}
 
-    heap_end_page
+/// Logs the heap counters and the outstanding allocations grouped by size.
+/// With the `heap_debug` feature, every live allocation is listed as well.
+pub fn dump() {
+    let stats = match alloc_kernel::heap_stats() {
+        Some(stats) => stats,
+        None => return
+    };
+
+    info!("Heap: {} KiB in use, {} KiB peak, {} live allocations ({} since boot)",
+          stats.bytes_in_use / 1024, stats.peak_bytes_in_use / 1024,
+          stats.live_allocations(), stats.allocations);
+
+    for group in alloc_kernel::outstanding_allocations().iter() {
+        if group.count > 0 {
+            info!("  up to {:>8} bytes: {} allocations, {} bytes", group.max_size,
+                  group.count, group.bytes);
+        }
+    }
+
+    for cache in alloc_kernel::cache_stats().iter() {
+        info!("  slab cache {:>4}: {} slabs, {} allocated, {} free", cache.object_size,
+              cache.slabs, cache.allocated, cache.free);
+    }
+
+    dump_live_allocations();
 }
+
+#[cfg(feature = "heap_debug")]
+fn dump_live_allocations() {
+    alloc_kernel::for_each_allocation(|address, size| {
+        info!("  {:#x}: {} bytes", address, size);
+    });
+}
+
+#[cfg(not(feature = "heap_debug"))]
+fn dump_live_allocations() {}
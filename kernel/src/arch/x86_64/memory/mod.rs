@@ -1,6 +1,8 @@
 use core::ops::Add;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use multiboot2::BootInformation;
-use self::frame_allocator::AreaFrameAllocator;
+use self::frame_allocator::BitmapFrameAllocator;
+use spin::{Mutex, MutexGuard, Once};
 
 pub use self::stack_allocator::Stack;
 
@@ -22,11 +24,22 @@ pub const RECURSIVE_PAGE_OFFSET: usize = (-(PML4_SIZE as isize)) as usize;
 pub const KERNEL_OFFSET: usize = RECURSIVE_PAGE_OFFSET - KERNEL_SIZE;
 
 pub const KERNEL_HEAP_START: usize = KERNEL_OFFSET + KERNEL_SIZE/2;
-pub const KERNEL_HEAP_SIZE: usize = 128 * 1024 * 1024; // 128 MB
+/// Size of the heap mapped at boot
+pub const KERNEL_HEAP_INITIAL_SIZE: usize = 1024 * 1024; // 1 MB
+/// The heap grows on demand, but never past this size
+pub const KERNEL_HEAP_MAX_SIZE: usize = 128 * 1024 * 1024; // 128 MB
+
+/// Offset of the direct mapping of all physical memory
+pub const PHYSICAL_MEMORY_OFFSET: usize = 0xffff_8000_0000_0000;
 
 pub const VGA_BUFFER: usize = 0xb8000;
 pub const PAGE_SIZE: usize = 0x1000;
 
+/// End of the physical memory reachable through `PHYSICAL_MEMORY_OFFSET`
+static PHYSICAL_MEMORY_END: AtomicUsize = ATOMIC_USIZE_INIT;
+
+static MEMORY_CONTROLLER: Once<Mutex<MemoryController>> = Once::new();
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Frame {
     pub number: usize,
@@ -141,9 +154,29 @@ impl Iterator for PageIter {
     }
 }
 
+/// Returns the address through which the given physical address can be
+/// accessed in the direct physical memory mapping.
+pub fn phys_to_virt(address: PhysicalAddress) -> VirtualAddress {
+    assert!(address < PHYSICAL_MEMORY_END.load(Ordering::SeqCst),
+            "Physical address {:#x} is not in the physical memory map", address);
+    address + PHYSICAL_MEMORY_OFFSET
+}
+
+/// Translates any mapped virtual address to its physical address, using a
+/// simple subtraction for addresses in the direct physical memory mapping.
+pub fn virt_to_phys(address: VirtualAddress) -> Option<PhysicalAddress> {
+    let end = PHYSICAL_MEMORY_OFFSET + PHYSICAL_MEMORY_END.load(Ordering::SeqCst);
+
+    if address >= PHYSICAL_MEMORY_OFFSET && address < end {
+        Some(address - PHYSICAL_MEMORY_OFFSET)
+    } else {
+        unsafe { paging::Mapper::new() }.translate(address)
+    }
+}
+
 pub struct MemoryController {
     active_table: paging::ActivePageTable,
-    frame_allocator: frame_allocator::AreaFrameAllocator,
+    frame_allocator: frame_allocator::BitmapFrameAllocator,
     stack_allocator: stack_allocator::StackAllocator,
 }
 
@@ -155,9 +188,22 @@ impl MemoryController {
         stack_allocator.alloc_stack(active_table, frame_allocator,
                                     size_in_pages)
     }
+
+    pub fn free_stack(&mut self, stack: Stack) {
+        let &mut MemoryController { ref mut active_table,
+                                    ref mut frame_allocator,
+                                    ref mut stack_allocator } = self;
+        stack_allocator.free_stack(active_table, frame_allocator, stack)
+    }
+}
+
+/// Locks the memory controller. Nothing may allocate from the kernel heap
+/// while holding it, since growing the heap needs the controller as well.
+pub fn controller() -> MutexGuard<'static, MemoryController> {
+    MEMORY_CONTROLLER.try().expect("Memory manager not initialized!").lock()
 }
 
-pub fn init(boot_info: &BootInformation) -> MemoryController {
+pub fn init(boot_info: &BootInformation) {
     assert_has_not_been_called!("memory::init must be called only once");
 
     let memory_map_tag = boot_info.memory_map_tag().expect(
@@ -180,28 +226,39 @@ pub fn init(boot_info: &BootInformation) -> MemoryController {
              multiboot_start,
              multiboot_end);
 
-    let mut frame_allocator = AreaFrameAllocator::new(
-            kernel_start as usize, kernel_end as usize, multiboot_start,
-            multiboot_end, memory_map_tag.memory_areas());
+    let mut frame_allocator = BitmapFrameAllocator::new(memory_map_tag.memory_areas());
+
+    // The ELF sections are linked at their higher half addresses
+    frame_allocator.reserve_range(kernel_start - KERNEL_OFFSET, kernel_end - KERNEL_OFFSET);
+    frame_allocator.reserve_range(multiboot_start, multiboot_end);
+
+    for module in boot_info.module_tags() {
+        frame_allocator.reserve_range(module.start_address() as usize,
+                                      module.end_address() as usize);
+    }
+
+    info!("{} MiB of free physical memory",
+          frame_allocator.free_frames() * PAGE_SIZE / 1024 / 1024);
 
     let mut active_page_table = paging::init(&mut frame_allocator, boot_info);
 
     let heap_end_page = heap::init(&mut active_page_table,
                                    &mut frame_allocator);
 
+    // Stacks get the rest of the kernel address space above the heap
     let stack_allocator = {
         let stack_alloc_start = heap_end_page + 1;
-        let stack_alloc_end = stack_alloc_start + 100;
+        let stack_alloc_end = Page::containing_address(RECURSIVE_PAGE_OFFSET - 1);
         let stack_alloc_range = Page::range_inclusive(stack_alloc_start,
                                                       stack_alloc_end);
         stack_allocator::StackAllocator::new(stack_alloc_range)
     };
 
-    ok!("Memory manager initialized.");
-
-    MemoryController {
+    MEMORY_CONTROLLER.call_once(|| Mutex::new(MemoryController {
         active_table: active_page_table,
         frame_allocator: frame_allocator,
         stack_allocator: stack_allocator,
-    }
+    }));
+
+    ok!("Memory manager initialized.");
 }
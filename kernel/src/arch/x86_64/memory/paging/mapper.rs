@@ -1,3 +1,4 @@
+use arch::cpuid;
 use arch::memory::{VirtualAddress, PhysicalAddress, Frame, Page, PAGE_SIZE};
 use core::ptr::Unique;
 use super::{ENTRY_COUNT, FrameAllocator};
@@ -90,27 +91,125 @@ impl Mapper {
         p1[page.p1_index()].set(frame, flags | PRESENT);
     }
 
+    /// Maps a 2 MiB page directly from its P2 entry. Both `page` and `frame`
+    /// need to be 2 MiB aligned.
+    pub fn map_huge_2m<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags,
+                          allocator: &mut A) where A: FrameAllocator {
+        assert!(page.p1_index() == 0, "2 MiB pages must be 2 MiB aligned");
+        assert!(frame.number % ENTRY_COUNT == 0, "2 MiB frames must be 2 MiB aligned");
+
+        let p3 = self.p4_mut().next_table_create(page.p4_index(), allocator);
+        let p2 = p3.next_table_create(page.p3_index(), allocator);
+
+        assert!(p2[page.p2_index()].is_unused());
+        p2[page.p2_index()].set(frame, flags | PRESENT | HUGE_PAGE);
+    }
+
+    /// Maps a 1 GiB page directly from its P3 entry. Both `page` and `frame`
+    /// need to be 1 GiB aligned, and the CPU needs to support 1 GiB pages.
+    pub fn map_huge_1g<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags,
+                          allocator: &mut A) where A: FrameAllocator {
+        assert!(cpuid::has_1gib_pages(), "The CPU does not support 1 GiB pages");
+        assert!(page.p2_index() == 0 && page.p1_index() == 0,
+                "1 GiB pages must be 1 GiB aligned");
+        assert!(frame.number % (ENTRY_COUNT * ENTRY_COUNT) == 0,
+                "1 GiB frames must be 1 GiB aligned");
+
+        let p3 = self.p4_mut().next_table_create(page.p4_index(), allocator);
+
+        assert!(p3[page.p3_index()].is_unused());
+        p3[page.p3_index()].set(frame, flags | PRESENT | HUGE_PAGE);
+    }
+
     pub fn identity_map<A>(&mut self, frame: Frame, flags: EntryFlags, allocator: &mut A)
             where A: FrameAllocator {
         let page = Page::containing_address(frame.start_address());
         self.map_to(page, frame, flags, allocator)
     }
 
+    /// Unmaps the given page, which can be a 2 MiB or 1 GiB page, and
+    /// deallocates all the frames backing it.
     pub fn unmap<A>(&mut self, page: Page, allocator: &mut A) where A: FrameAllocator
     {
+        let (frame, count) = self.unmap_entry(page, allocator);
+
+        for number in frame.number..(frame.number + count) {
+            allocator.deallocate_frame(Frame { number: number });
+        }
+    }
+
+    /// Unmaps the given page and frees any page tables left empty, but hands
+    /// the (first) mapped frame back to the caller instead of deallocating it.
+    pub fn unmap_return<A>(&mut self, page: Page, allocator: &mut A) -> Frame
+            where A: FrameAllocator {
+        self.unmap_entry(page, allocator).0
+    }
+
+    /// Returns the first frame of the mapping and how many 4 KiB frames it spans
+    fn unmap_entry<A>(&mut self, page: Page, allocator: &mut A) -> (Frame, usize)
+            where A: FrameAllocator {
         assert!(self.translate(page.start_address()).is_some());
 
-        let p1 = self.p4_mut()
-                     .next_table_mut(page.p4_index())
-                     .and_then(|p3| p3.next_table_mut(page.p3_index()))
-                     .and_then(|p2| p2.next_table_mut(page.p2_index()))
-                     .expect("mapping code does not support huge pages");
-        let frame = p1[page.p1_index()].frame().unwrap();
-        p1[page.p1_index()].set_unused();
+        let mapping = {
+            let p3 = self.p4_mut()
+                         .next_table_mut(page.p4_index())
+                         .expect("P3 table missing");
+
+            if p3[page.p3_index()].flags().contains(HUGE_PAGE) {
+                assert!(page.p2_index() == 0 && page.p1_index() == 0,
+                        "Attempted to unmap part of a 1 GiB page");
+                (take_frame(&mut p3[page.p3_index()]), ENTRY_COUNT * ENTRY_COUNT)
+            } else {
+                let p2 = p3.next_table_mut(page.p3_index()).expect("P2 table missing");
+
+                if p2[page.p2_index()].flags().contains(HUGE_PAGE) {
+                    assert!(page.p1_index() == 0, "Attempted to unmap part of a 2 MiB page");
+                    (take_frame(&mut p2[page.p2_index()]), ENTRY_COUNT)
+                } else {
+                    let p1 = p2.next_table_mut(page.p2_index()).expect("P1 table missing");
+                    (take_frame(&mut p1[page.p1_index()]), 1)
+                }
+            }
+        };
+
+        // This also invalidates huge pages as a whole
         unsafe {
             tlb::flush(page.start_address());
         }
-        // TODO free p(1,2,3) table if empty
-        allocator.deallocate_frame(frame);
+
+        self.free_empty_tables(page, allocator);
+
+        mapping
     }
+
+    /// Frees the P1, P2 and P3 tables on the path to `page`, from the bottom
+    /// up, as long as they don't have any entries left.
+    fn free_empty_tables<A>(&mut self, page: Page, allocator: &mut A) where A: FrameAllocator {
+        let p4 = self.p4_mut();
+
+        {
+            let p3 = p4.next_table_mut(page.p4_index()).expect("P3 table missing");
+
+            // There is no P2 table left if we just unmapped a 1 GiB page
+            if let Some(p2) = p3.next_table_mut(page.p3_index()) {
+                if p2.next_table(page.p2_index()).map_or(false, |p1| p1.is_empty()) {
+                    p2.free_next_table(page.p2_index(), allocator);
+                }
+            }
+
+            if p3.next_table(page.p3_index()).map_or(false, |p2| p2.is_empty()) {
+                p3.free_next_table(page.p3_index(), allocator);
+            }
+        }
+
+        if p4.next_table(page.p4_index()).map_or(false, |p3| p3.is_empty()) {
+            p4.free_next_table(page.p4_index(), allocator);
+        }
+    }
+}
+
+fn take_frame(entry: &mut Entry) -> Frame {
+    let frame = entry.frame().unwrap();
+    entry.set_unused();
+    frame
 }
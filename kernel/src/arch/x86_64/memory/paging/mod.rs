@@ -6,12 +6,15 @@ mod temp_page;
 pub use self::entry::*;
 pub use self::mapper::Mapper;
 
-use arch::memory::{Frame, Page, PAGE_SIZE, VGA_BUFFER};
+use arch::cpuid;
+use arch::memory::{Frame, Page, PhysicalAddress, PAGE_SIZE, VGA_BUFFER};
 use arch::memory::frame_allocator::FrameAllocator;
+use core::cmp::min;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::Ordering;
 use multiboot2::BootInformation;
 use self::temp_page::TemporaryPage;
-use super::KERNEL_OFFSET;
+use super::{KERNEL_OFFSET, PHYSICAL_MEMORY_END, PHYSICAL_MEMORY_OFFSET};
 
 pub const MAX_FRAMES: usize = 8388608; // 32 GB of physical memory
 
@@ -42,6 +45,9 @@ impl ActivePageTable {
         }
     }
 
+    /// Runs `f` with the recursive mapping pointing at `table`, so that the
+    /// mapper modifies the inactive table instead (including freeing any of
+    /// its page tables that `unmap` leaves empty).
     pub fn with<F>(&mut self, table: &mut InactivePageTable,
                    temporary_page: &mut TemporaryPage, f: F)
             where F: FnOnce(&mut ActivePageTable) {
@@ -153,18 +159,14 @@ pub fn init<A>(allocator: &mut A, boot_info: &BootInformation) -> ActivePageTabl
         let multiboot_start = Frame::containing_address(boot_info.start_address());
         let multiboot_end = Frame::containing_address(boot_info.end_address() - 1);
 
-        // identity map the multiboot mudules list
-        for module in boot_info.module_tags() {
-            let module_start = Frame::containing_address(module.start_address() as usize);
-            let module_end = Frame::containing_address(module.end_address() as usize - 1);
-            info!("Mapping module {} from: {:#x} to: {:#x}" ,
-                module.name(), module.start_address(), module.end_address());
-            for frame in Frame::range_inclusive(module_start, module_end) {
-                if frame < multiboot_start || frame > multiboot_end {
-                    mapper.identity_map(frame, PRESENT, allocator);
-                }
-            }
-        }
+        // map all of physical memory, which also makes the multiboot modules reachable
+        let memory_end = boot_info.memory_map_tag()
+            .expect("Memory map tag required")
+            .memory_areas()
+            .map(|area| (area.base_addr + area.length) as usize)
+            .max()
+            .expect("No usable memory areas");
+        map_physical_memory(mapper, min(memory_end, MAX_FRAMES * PAGE_SIZE), allocator);
 
         // identity map the multiboot info structure
         for frame in Frame::range_inclusive(multiboot_start, multiboot_end) {
@@ -182,3 +184,35 @@ pub fn init<A>(allocator: &mut A, boot_info: &BootInformation) -> ActivePageTabl
 
     active_table
 }
+
+/// Maps the physical range `0..end` at `PHYSICAL_MEMORY_OFFSET`, using the
+/// largest pages the CPU supports.
+fn map_physical_memory<A>(mapper: &mut ActivePageTable, end: PhysicalAddress, allocator: &mut A)
+        where A: FrameAllocator {
+    let use_1g_pages = cpuid::has_1gib_pages();
+    let page_size = if use_1g_pages {
+        PAGE_SIZE * ENTRY_COUNT * ENTRY_COUNT
+    } else {
+        PAGE_SIZE * ENTRY_COUNT
+    };
+    let flags = WRITABLE | GLOBAL | NO_EXECUTE;
+
+    let mut address = 0;
+    while address < end {
+        let page = Page::containing_address(address + PHYSICAL_MEMORY_OFFSET);
+        let frame = Frame::containing_address(address);
+
+        if use_1g_pages {
+            mapper.map_huge_1g(page, frame, flags, allocator);
+        } else {
+            mapper.map_huge_2m(page, frame, flags, allocator);
+        }
+
+        address += page_size;
+    }
+
+    PHYSICAL_MEMORY_END.store(address, Ordering::SeqCst);
+
+    info!(" - Mapped {} MiB of physical memory at {:#x}", address / 1024 / 1024,
+          PHYSICAL_MEMORY_OFFSET);
+}
@@ -3,6 +3,7 @@ use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
 use super::ENTRY_COUNT;
 use super::entry::*;
+use x86::shared::tlb;
 
 pub const P4: *mut Table<Level4> = 0xffffffff_fffff000 as *mut _;
 
@@ -43,6 +44,10 @@ impl<L> Table<L> where L: TableLevel {
             entry.set_unused();
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|entry| entry.is_unused())
+    }
 }
 
 impl<L> Table<L> where L: HierarchicalLevel {
@@ -79,6 +84,24 @@ impl<L> Table<L> where L: HierarchicalLevel {
 
         self.next_table_mut(index).unwrap()
     }
+
+    /// Unlinks the next level table at `index` and returns its frame to the
+    /// allocator. The table must not have any entries left.
+    pub fn free_next_table<A>(&mut self, index: usize, allocator: &mut A) where A: FrameAllocator {
+        let address = self.next_table_address(index)
+                .expect("Attempted to free a missing or huge page table!");
+
+        assert!(self.next_table(index).unwrap().is_empty(),
+                "Attempted to free a page table that is still in use!");
+
+        let frame = self.entries[index].frame().unwrap();
+        self.entries[index].set_unused();
+
+        // The table was reachable through the recursive mapping
+        unsafe { tlb::flush(address) };
+
+        allocator.deallocate_frame(frame);
+    }
 }
 
 impl<L> Index<usize> for Table<L> where L: TableLevel {
@@ -29,9 +29,11 @@ impl TemporaryPage {
         self.page.start_address()
     }
 
-    /// Unmaps the temporary page in the active table.
+    /// Unmaps the temporary page in the active table. The frame is only
+    /// borrowed, so it is not deallocated, but the page tables are returned
+    /// to the tiny allocator.
     pub fn unmap(&mut self, active_table: &mut ActivePageTable) {
-        active_table.unmap(self.page, &mut self.allocator)
+        active_table.unmap_return(self.page, &mut self.allocator);
     }
 
     pub fn map_table_frame(&mut self, frame: Frame, active_table: &mut ActivePageTable)
@@ -51,6 +51,22 @@ impl StackAllocator {
             _ => None, /* not enough pages */
         }
     }
+
+    /// Unmaps the stack and frees its frames. The virtual address range
+    /// (including the guard page) isn't reused: the stack range covers the
+    /// almost 256 GiB of kernel address space above the heap, which is enough
+    /// for millions of stacks.
+    pub fn free_stack<FA: FrameAllocator>(&mut self,
+                                          active_table: &mut ActivePageTable,
+                                          frame_allocator: &mut FA,
+                                          stack: Stack) {
+        let start = Page::containing_address(stack.bottom());
+        let end = Page::containing_address(stack.top() - 1);
+
+        for page in Page::range_inclusive(start, end) {
+            active_table.unmap(page, frame_allocator);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -75,4 +91,9 @@ impl Stack {
     pub fn bottom(&self) -> usize {
         self.bottom
     }
+
+    /// Whether `address` is in the unmapped guard page right below the stack
+    pub fn guard_page_contains(&self, address: usize) -> bool {
+        address < self.bottom && address >= self.bottom - PAGE_SIZE
+    }
 }
@@ -2,14 +2,20 @@ pub use self::start::kernel_start;
 
 #[macro_use]
 pub mod vga;
+#[macro_use]
+pub mod serial;
 
 pub mod clock;
 pub mod cmos;
+pub mod cpuid;
+pub mod fpu;
 pub mod interrupts;
 pub mod io;
 pub mod initrd;
+pub mod keyboard;
 pub mod memory;
 pub mod nmi;
 pub mod pit;
+pub mod ps2;
 pub mod start;
 pub mod tasking;
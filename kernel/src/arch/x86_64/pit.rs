@@ -1,5 +1,200 @@
-pub fn init() {
+use arch::{clock, interrupts};
+use arch::io::Port;
+use core::cmp;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+use spin::Mutex;
+use time::Duration;
+
+/// Frequency of the oscillator driving the PIT, in Hz
+pub const BASE_FREQUENCY: u32 = 1193182;
+
+const PIT_IRQ: u8 = 0;
+
+/// Channel 0, access mode lobyte/hibyte, mode 3 (square wave generator), binary
+const CHANNEL0_SQUARE_WAVE: u8 = 0b0011_0110;
+/// Channel 0, access mode lobyte/hibyte, mode 0 (interrupt on terminal count), binary
+const CHANNEL0_ONE_SHOT: u8 = 0b0011_0000;
+/// Latches the current count of channel 0, so it can be read
+const CHANNEL0_LATCH: u8 = 0b0000_0000;
+
+static CHANNEL0: Mutex<Port<u8>> = Mutex::new(unsafe { Port::new(0x40) });
+static COMMAND: Mutex<Port<u8>> = Mutex::new(unsafe { Port::new(0x43) });
+
+static TICKS: AtomicUsize = ATOMIC_USIZE_INIT;
+static FREQUENCY: AtomicUsize = ATOMIC_USIZE_INIT;
+static DIVISOR: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Whether the idle task may stop the periodic tick
+static TICKLESS: AtomicBool = ATOMIC_BOOL_INIT;
+/// PIT cycles the running one-shot countdown was started with, 0 while
+/// ticking periodically
+static ONE_SHOT_CYCLES: AtomicUsize = ATOMIC_USIZE_INIT;
+/// Cycles of the current tick that had already passed when the one-shot
+/// countdown was started
+static CARRIED_CYCLES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub fn init(frequency: u32) {
     assert_has_not_been_called!("pit::init must be called only once");
 
-    // TODO: Port the PIT code from the old kernel
+    set_frequency(frequency);
+    set_tickless(cfg!(feature = "tickless"));
+
+    interrupts::register_irq_handler(PIT_IRQ, handle_irq)
+        .expect("Unable to register the PIT interrupt handler!");
+
+    ok!("PIT initialized at {} Hz.", self::frequency());
+}
+
+/// Programs channel 0 to fire IRQ 0 at (approximately) the given frequency.
+pub fn set_frequency(frequency: u32) {
+    // The reload value is 16 bits wide, with 0 meaning 65536
+    assert!(frequency > BASE_FREQUENCY / 65536 && frequency <= BASE_FREQUENCY,
+            "Unsupported PIT frequency: {} Hz", frequency);
+
+    let divisor = BASE_FREQUENCY / frequency;
+
+    interrupts::without_interrupts(|| {
+        start_periodic(divisor as u16);
+
+        DIVISOR.store(divisor as usize, Ordering::SeqCst);
+        FREQUENCY.store((BASE_FREQUENCY / divisor) as usize, Ordering::SeqCst);
+    });
+}
+
+fn start_periodic(divisor: u16) {
+    COMMAND.lock().write(CHANNEL0_SQUARE_WAVE);
+
+    let mut channel0 = CHANNEL0.lock();
+    channel0.write(divisor as u8);
+    channel0.write((divisor >> 8) as u8);
+}
+
+/// Allows the idle task to stop the periodic tick while nothing needs to run.
+/// Enabled at boot with the `tickless` feature.
+pub fn set_tickless(enabled: bool) {
+    TICKLESS.store(enabled, Ordering::SeqCst);
+}
+
+/// Stops the periodic interrupt for up to `ticks` ticks, if tickless mode is
+/// enabled, and fires a single interrupt instead once they are over. The
+/// countdown is 16 bits wide, so fewer ticks than asked for might be skipped.
+/// Must be called with interrupts disabled and followed by `resume_ticks`
+/// once the CPU wakes up.
+pub fn skip_ticks(ticks: usize) {
+    // A countdown might still be finishing a partial tick from last time
+    if !TICKLESS.load(Ordering::SeqCst) || ONE_SHOT_CYCLES.load(Ordering::SeqCst) > 0 {
+        return;
+    }
+
+    let divisor = DIVISOR.load(Ordering::SeqCst);
+    let ticks = cmp::min(ticks, 0xFFFF / divisor);
+    if ticks <= 1 {
+        return;
+    }
+
+    start_one_shot(ticks * divisor, 0);
+}
+
+fn start_one_shot(cycles: usize, carried: usize) {
+    COMMAND.lock().write(CHANNEL0_ONE_SHOT);
+
+    let mut channel0 = CHANNEL0.lock();
+    channel0.write(cycles as u8);
+    channel0.write((cycles >> 8) as u8);
+
+    CARRIED_CYCLES.store(carried, Ordering::SeqCst);
+    ONE_SHOT_CYCLES.store(cycles, Ordering::SeqCst);
+}
+
+/// Accounts for the ticks that passed since `skip_ticks` and goes back to
+/// periodic ticks, once the current tick is over. Must be called with
+/// interrupts disabled.
+pub fn resume_ticks() {
+    let count = ONE_SHOT_CYCLES.swap(0, Ordering::SeqCst);
+    if count == 0 {
+        return;
+    }
+
+    let divisor = DIVISOR.load(Ordering::SeqCst);
+
+    let remaining = {
+        COMMAND.lock().write(CHANNEL0_LATCH);
+
+        let mut channel0 = CHANNEL0.lock();
+        let low = channel0.read() as usize;
+        let high = channel0.read() as usize;
+        high << 8 | low
+    };
+
+    // The counter wraps around once the countdown is over
+    let elapsed = if remaining > count { count } else { count - remaining };
+    let cycles = CARRIED_CYCLES.load(Ordering::SeqCst) + elapsed;
+    let partial = cycles % divisor;
+
+    // Finish the partial tick with a shorter countdown before ticking
+    // periodically again, so no time gets lost
+    if partial > 0 {
+        start_one_shot(divisor - partial, partial);
+    } else {
+        start_periodic(divisor as u16);
+    }
+
+    advance(cycles / divisor);
+}
+
+/// The actual frequency the PIT is running at, in Hz
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::SeqCst) as u32
+}
+
+/// Number of timer interrupts since the PIT was initialized
+pub fn ticks() -> usize {
+    TICKS.load(Ordering::SeqCst)
+}
+
+/// The number of ticks `duration` takes at the current frequency, rounded up.
+/// Durations too long to count in ticks, like one used to mean "forever",
+/// come out as `usize::max_value()`.
+pub fn duration_to_ticks(duration: Duration) -> usize {
+    let frequency = frequency() as u64;
+    let ticks = duration.as_secs().saturating_mul(frequency).saturating_add(
+        (duration.subsec_nanos() as u64 * frequency + 999_999_999) / 1_000_000_000);
+
+    if ticks > usize::max_value() as u64 {
+        usize::max_value()
+    } else {
+        ticks as usize
+    }
+}
+
+/// The time `ticks` ticks take at the current frequency
+pub fn ticks_to_duration(ticks: usize) -> Duration {
+    let frequency = frequency() as u64;
+    if frequency == 0 {
+        return Duration::default();
+    }
+
+    let ticks = ticks as u64;
+    Duration::new(ticks / frequency, ((ticks % frequency) * 1_000_000_000 / frequency) as u32)
+}
+
+fn handle_irq() {
+    if ONE_SHOT_CYCLES.load(Ordering::SeqCst) > 0 {
+        // The one-shot countdown is over
+        resume_ticks();
+    } else {
+        advance(1);
+    }
+}
+
+fn advance(ticks: usize) {
+    TICKS.fetch_add(ticks, Ordering::SeqCst);
+
+    for _ in 0..ticks {
+        clock::tick();
+    }
+    // Timers compare their deadlines against `ticks()`, and tasks are charged
+    // for CPU time from it as well, so only the time slice needs the count
+    ::timer::tick();
+    ::tasking::tick(ticks);
 }
@@ -0,0 +1,143 @@
+use arch::io::Port;
+use spin::Mutex;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const CONFIG_FIRST_PORT_INTERRUPT: u8 = 1 << 0;
+const CONFIG_SECOND_PORT_INTERRUPT: u8 = 1 << 1;
+const CONFIG_FIRST_PORT_TRANSLATION: u8 = 1 << 6;
+
+const TEST_PASSED: u8 = 0x55;
+const DEVICE_ACK: u8 = 0xFA;
+
+/// Number of status polls before giving up on the controller or device
+const TIMEOUT: usize = 100_000;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum Command {
+    ReadConfig = 0x20,
+    WriteConfig = 0x60,
+    DisableSecondPort = 0xA7,
+    EnableSecondPort = 0xA8,
+    TestController = 0xAA,
+    DisableFirstPort = 0xAD,
+    EnableFirstPort = 0xAE
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum DeviceCommand {
+    SetDefaults = 0xF6,
+    EnableScanning = 0xF4,
+    DisableScanning = 0xF5
+}
+
+pub static CONTROLLER: Mutex<Controller> = Mutex::new(unsafe { Controller::new() });
+
+/// The 8042 PS/2 controller
+pub struct Controller {
+    data: Port<u8>,
+    // Reads return the status register, writes send a controller command
+    command: Port<u8>
+}
+
+impl Controller {
+    const unsafe fn new() -> Controller {
+        Controller { data: Port::new(0x60), command: Port::new(0x64) }
+    }
+
+    /// Sets up the controller with the keyboard on the first port. The
+    /// controller translates the keyboard's scancodes to set 1, and IRQ 1
+    /// is enabled, so a handler must be registered before unmasking it.
+    pub fn init(&mut self) -> Result<(), &'static str> {
+        // Disable both devices so they can't send data during initialization
+        self.command(Command::DisableFirstPort)?;
+        self.command(Command::DisableSecondPort)?;
+
+        // Flush the output buffer
+        while self.status() & STATUS_OUTPUT_FULL != 0 {
+            self.data.read();
+        }
+
+        let mut config = self.config()?;
+        config &= !(CONFIG_FIRST_PORT_INTERRUPT | CONFIG_SECOND_PORT_INTERRUPT);
+        self.set_config(config)?;
+
+        self.command(Command::TestController)?;
+        if self.read()? != TEST_PASSED {
+            return Err("PS/2 controller self test failed");
+        }
+
+        // The self test can reset the controller, so write the config again
+        self.set_config(config)?;
+
+        self.command(Command::EnableFirstPort)?;
+
+        self.device_command(DeviceCommand::SetDefaults)?;
+        self.device_command(DeviceCommand::EnableScanning)?;
+
+        self.set_config(config | CONFIG_FIRST_PORT_INTERRUPT | CONFIG_FIRST_PORT_TRANSLATION)?;
+
+        Ok(())
+    }
+
+    fn status(&mut self) -> u8 {
+        self.command.read()
+    }
+
+    fn wait_for(&mut self, mask: u8, set: bool) -> Result<(), &'static str> {
+        for _ in 0..TIMEOUT {
+            if (self.status() & mask != 0) == set {
+                return Ok(());
+            }
+        }
+
+        Err("Timed out waiting for the PS/2 controller")
+    }
+
+    fn read(&mut self) -> Result<u8, &'static str> {
+        self.wait_for(STATUS_OUTPUT_FULL, true)?;
+        Ok(self.data.read())
+    }
+
+    fn write(&mut self, value: u8) -> Result<(), &'static str> {
+        self.wait_for(STATUS_INPUT_FULL, false)?;
+        self.data.write(value);
+        Ok(())
+    }
+
+    fn command(&mut self, command: Command) -> Result<(), &'static str> {
+        self.wait_for(STATUS_INPUT_FULL, false)?;
+        self.command.write(command as u8);
+        Ok(())
+    }
+
+    fn device_command(&mut self, command: DeviceCommand) -> Result<(), &'static str> {
+        self.write(command as u8)?;
+
+        if self.read()? == DEVICE_ACK {
+            Ok(())
+        } else {
+            Err("PS/2 device did not acknowledge the command")
+        }
+    }
+
+    fn config(&mut self) -> Result<u8, &'static str> {
+        self.command(Command::ReadConfig)?;
+        self.read()
+    }
+
+    fn set_config(&mut self, config: u8) -> Result<(), &'static str> {
+        self.command(Command::WriteConfig)?;
+        self.write(config)
+    }
+
+    /// Reads a byte the controller has already signalled with an IRQ
+    pub fn read_data(&mut self) -> u8 {
+        self.data.read()
+    }
+}
@@ -0,0 +1,131 @@
+use arch::io::Port;
+use core::fmt;
+use tasking::sync::IrqMutex;
+
+/***** MACROS *****/
+
+macro_rules! serial_println {
+    ($fmt:expr) => (serial_print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (serial_print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+macro_rules! serial_print {
+    ($($arg:tt)*) => ({
+        $crate::arch::serial::print(format_args!($($arg)*));
+    });
+}
+
+/***** CONSTANTS *****/
+
+const COM1_BASE: u16 = 0x3F8;
+const COM2_BASE: u16 = 0x2F8;
+
+/// The UART clock is 115200 Hz, so this gives us 38400 baud
+const BAUD_RATE_DIVISOR: u16 = 3;
+
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+const LINE_CONTROL_8N1: u8 = 0b0000_0011;
+
+/// Enable and clear both FIFOs, with a 14 byte interrupt threshold
+const FIFO_CONTROL_ENABLE: u8 = 0b1100_0111;
+
+/// Data terminal ready, request to send, and auxiliary output 2 (IRQs enabled)
+const MODEM_CONTROL_READY: u8 = 0b0000_1011;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/***** GLOBAL VARIABLES *****/
+
+/// Log records are mirrored to COM1 from interrupt and exception handlers, so
+/// an interrupt must not come in while a task holds the port
+pub static COM1: IrqMutex<SerialPort> = IrqMutex::new(unsafe { SerialPort::new(COM1_BASE) });
+pub static COM2: IrqMutex<SerialPort> = IrqMutex::new(unsafe { SerialPort::new(COM2_BASE) });
+
+/***** STRUCTS *****/
+
+/// A 16550 UART
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>
+}
+
+impl SerialPort {
+    pub const unsafe fn new(base: u16) -> SerialPort {
+        SerialPort {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5)
+        }
+    }
+
+    pub fn init(&mut self) {
+        self.interrupt_enable.write(0);
+
+        // With DLAB set, the data and interrupt enable registers hold the baud rate divisor
+        self.line_control.write(LINE_CONTROL_DLAB);
+        self.data.write(BAUD_RATE_DIVISOR as u8);
+        self.interrupt_enable.write((BAUD_RATE_DIVISOR >> 8) as u8);
+
+        self.line_control.write(LINE_CONTROL_8N1);
+        self.fifo_control.write(FIFO_CONTROL_ENABLE);
+        self.modem_control.write(MODEM_CONTROL_READY);
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        while self.line_status.read() & LINE_STATUS_TRANSMIT_EMPTY == 0 {}
+
+        self.data.write(byte);
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        if self.line_status.read() & LINE_STATUS_DATA_READY != 0 {
+            Some(self.data.read())
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for byte in string.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+/***** FUNCTIONS *****/
+
+pub fn init() {
+    assert_has_not_been_called!("serial::init must be called only once");
+
+    COM1.lock().init();
+    COM2.lock().init();
+}
+
+/// Prints to COM1. Interrupts are off while the port is held, so it can only
+/// be busy if we faulted or panicked in the middle of printing. Rather than
+/// deadlock, we write to the port anyway then.
+pub fn print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    match COM1.try_lock() {
+        Some(mut com1) => com1.write_fmt(args).unwrap(),
+        None => {
+            let mut com1 = unsafe { SerialPort::new(COM1_BASE) };
+            com1.write_fmt(args).unwrap();
+        }
+    }
+}
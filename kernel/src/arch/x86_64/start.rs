@@ -8,15 +8,18 @@ pub extern "C" fn kernel_start(multiboot_address: usize) {
     enable_write_protect_bit();
 
     vga::init();
+    serial::init();
 
     ok!("Kernel started.");
 
-    clock::init();
-
     let boot_info = unsafe { multiboot2::load(multiboot_address) };
-    let mut memory_controller = memory::init(boot_info);
+    memory::init(boot_info);
 
-    interrupts::init(&mut memory_controller);
+    interrupts::init();
+    fpu::init();
+
+    clock::init();
+    keyboard::init();
 
     // TODO: Other initialization code here
 
@@ -1,3 +1,4 @@
+use arch::fpu::FpuState;
 use arch::memory::VirtualAddress;
 
 pub struct Context {
@@ -18,14 +19,32 @@ pub struct Context {
     /// Base pointer
     rbp: usize,
     /// Stack pointer
-    rsp: usize
+    rsp: usize,
+    /// x87/SSE registers
+    fpu: FpuState
 }
 
 impl Context {
+    /// Saves the current state into `self` and resumes `next`. Must be called
+    /// with interrupts disabled, so that an interrupt handler can't run with
+    /// half of the FPU state switched.
+    #[inline(never)]
+    pub unsafe fn switch_to(&mut self, next: &mut Context) {
+        self.fpu.save();
+        next.fpu.restore();
+
+        self.switch_registers(next);
+    }
+
+    /// This is safe to call from an interrupt handler: interrupts are disabled
+    /// while the registers are swapped, and the flags of `next` (including its
+    /// interrupt flag) are only restored once its stack is in place.
     #[cold]
     #[inline(never)]
     #[naked]
-    pub unsafe fn switch_to(&mut self, next: &mut Context) {
+    unsafe fn switch_registers(&mut self, next: &mut Context) {
+        asm!("pushfq ; pop $0 ; cli" : "=r"(self.rflags) : : "memory" : "intel", "volatile");
+
         asm!("mov $0, cr3" : "=r"(self.cr3) : : "memory" : "intel", "volatile");
         if next.cr3 == 0 {
             panic!("Attempted to switch to a task with an invalid page table!");
@@ -33,9 +52,6 @@ impl Context {
             asm!("mov cr3, $0" : : "r"(next.cr3) : "memory" : "intel", "volatile");
         }
 
-        asm!("pushfq ; pop $0" : "=r"(self.rflags) : : "memory" : "intel", "volatile");
-        asm!("push $0 ; popfq" : : "r"(next.rflags) : "memory" : "intel", "volatile");
-
         asm!("mov $0, rbx" : "=r"(self.rbx) : : "memory" : "intel", "volatile");
         asm!("mov rbx, $0" : : "r"(next.rbx) : "memory" : "intel", "volatile");
 
@@ -56,12 +72,14 @@ impl Context {
 
         asm!("mov $0, rbp" : "=r"(self.rbp) : : "memory" : "intel", "volatile");
         asm!("mov rbp, $0" : : "r"(next.rbp) : "memory" : "intel", "volatile");
+
+        asm!("push $0 ; popfq" : : "r"(next.rflags) : "memory" : "intel", "volatile");
     }
 
-    pub const fn new() -> Context {
+    pub fn new() -> Context {
         Context {
             cr3: 0, rflags: 0, rbx: 0, r12: 0, r13: 0, r14: 0, r15: 0,
-            rbp: 0, rsp: 0
+            rbp: 0, rsp: 0, fpu: FpuState::new()
         }
     }
 
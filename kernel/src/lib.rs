@@ -39,16 +39,21 @@ mod arch;
 pub mod time;
 mod runtime;
 mod tasking;
+mod timer;
 mod filesystem;
 
 
 fn kernel_main() {
-    tasking::init();
+    tasking::init(box tasking::RoundRobin);
+    timer::init();
+
+    #[cfg(feature = "self_test")]
+    tasking::self_test::run();
 
     println!("Hello, Rust kernel world!");
 
-    tasking::spawn(separate_task);
-    tasking::spawn(task_2);
+    tasking::spawn_named("separate_task", separate_task);
+    tasking::spawn_named("task_2", task_2);
     tasking::switch();
     println!("Back in main.");
 
@@ -1,13 +1,13 @@
+use arch::serial;
 use arch::vga::{print, print_colored, Color};
+use core::fmt;
 
 macro_rules! log {
     ($status:expr, $color:expr, $fmt:expr) => {
-        $crate::logging::status($status, $color);
-        println!($fmt);
+        $crate::logging::log($status, $color, format_args!(concat!($fmt, "\n")));
     };
     ($status:expr, $color:expr, $fmt:expr, $($arg:tt)*) => {
-        $crate::logging::status($status, $color);
-        println!($fmt, $($arg)*);
+        $crate::logging::log($status, $color, format_args!(concat!($fmt, "\n"), $($arg)*));
     };
 }
 
@@ -31,6 +31,14 @@ macro_rules! fail {
     ($fmt:expr, $($arg:tt)*) => (log!("FAIL", $crate::arch::vga::Color::Red, $fmt, $($arg)*));
 }
 
+/// Writes a log record to the screen and mirrors it to the serial port.
+pub fn log(label: &str, color: Color, args: fmt::Arguments) {
+    status(label, color);
+    print(args);
+
+    serial::print(format_args!("[{}] {}", label, args));
+}
+
 pub fn status(label: &str, color: Color) {
     print(format_args!("["));
     print_colored(format_args!("{}", label), color);
@@ -1,3 +1,4 @@
+use arch::interrupts;
 use core;
 
 #[lang = "eh_personality"]
@@ -6,9 +7,13 @@ extern fn eh_personality() {}
 #[lang = "panic_fmt"]
 #[no_mangle]
 pub extern fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
+    // Serial first, in case the screen is what we panicked on
+    serial_println!("\n\nPANIC in {} at line {}:", file, line);
+    serial_println!("    {}", fmt);
     println!("\n\nPANIC in {} at line {}:", file, line);
     println!("    {}", fmt);
-    loop {}
+
+    interrupts::halt_forever();
 }
 
 #[allow(non_snake_case)]
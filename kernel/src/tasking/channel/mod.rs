@@ -0,0 +1,277 @@
+//! Multi-producer, single-consumer channels for passing messages between
+//! tasks. Receiving parks the task on a wait queue until a message arrives.
+//!
+//! `SyncSender::try_send` and `oneshot::Sender::send` never block or allocate,
+//! so interrupt handlers can use them to hand work off to a task. Sending on
+//! an unbounded channel may have to grow its buffer, so that is only allowed
+//! in task context.
+
+use alloc::arc::Arc;
+use alloc::VecDeque;
+use core::mem;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tasking::WaitQueue;
+use tasking::sync::IrqMutex;
+
+pub mod oneshot;
+
+/// The receiver is gone. Contains the message that couldn't be sent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full
+    Full(T),
+    /// The receiver is gone
+    Disconnected(T)
+}
+
+/// All senders are gone and no messages are left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No message is available right now
+    Empty,
+    /// All senders are gone and no messages are left
+    Disconnected
+}
+
+struct Channel<T> {
+    queue: IrqMutex<VecDeque<T>>,
+    /// Maximum number of queued messages, `None` for unbounded channels
+    bound: Option<usize>,
+    /// The receiver waits here for messages
+    receiver_waiters: WaitQueue,
+    /// Senders wait here for room in a full bounded channel
+    sender_waiters: WaitQueue,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool
+}
+
+/// The sending half of an unbounded channel. Can be cloned to send from
+/// several tasks.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>
+}
+
+/// The sending half of a bounded channel. Can be cloned to send from several
+/// tasks.
+pub struct SyncSender<T> {
+    channel: Arc<Channel<T>>
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>
+}
+
+/// Creates a channel that can hold any number of messages.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Channel::new(VecDeque::new(), None);
+
+    (Sender { channel: channel.clone() }, Receiver { channel: channel })
+}
+
+/// Creates a channel that holds at most `bound` messages. The buffer is
+/// allocated up front, so sending never allocates.
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    assert!(bound > 0, "A channel must be able to hold at least one message");
+
+    let channel = Channel::new(VecDeque::with_capacity(bound), Some(bound));
+
+    (SyncSender { channel: channel.clone() }, Receiver { channel: channel })
+}
+
+impl<T> Channel<T> {
+    fn new(queue: VecDeque<T>, bound: Option<usize>) -> Arc<Channel<T>> {
+        Arc::new(Channel {
+            queue: IrqMutex::new(queue),
+            bound: bound,
+            receiver_waiters: WaitQueue::new(),
+            sender_waiters: WaitQueue::new(),
+            senders: AtomicUsize::new(1),
+            receiver_alive: AtomicBool::new(true)
+        })
+    }
+
+    /// Queues `value` if there is room without growing the buffer.
+    fn try_push(&self, value: T) -> Result<(), TrySendError<T>> {
+        {
+            let mut queue = self.queue.lock();
+
+            if !self.receiver_alive.load(Ordering::SeqCst) {
+                return Err(TrySendError::Disconnected(value));
+            }
+
+            let full = match self.bound {
+                Some(bound) => queue.len() >= bound,
+                None => queue.len() >= queue.capacity()
+            };
+            if full {
+                return Err(TrySendError::Full(value));
+            }
+
+            queue.push_back(value);
+        }
+
+        self.receiver_waiters.wake_one();
+        Ok(())
+    }
+
+    /// Doubles the buffer of an unbounded channel. The new buffer is
+    /// allocated with interrupts enabled, since the heap can't be used while
+    /// the queue is locked.
+    fn grow(&self) {
+        let capacity = self.queue.lock().capacity();
+        let mut buffer = VecDeque::with_capacity(capacity * 2 + 1);
+
+        {
+            let mut queue = self.queue.lock();
+
+            // Another sender might have grown it in the meantime
+            if queue.capacity() == capacity {
+                buffer.extend(queue.drain(..));
+                mem::swap(&mut *queue, &mut buffer);
+            }
+        }
+
+        // Whichever buffer is left over gets freed here, with interrupts enabled
+    }
+
+    fn try_pop(&self) -> Result<T, TryRecvError> {
+        // Checked first, so that a message sent right before the last sender
+        // went away isn't missed
+        let disconnected = self.senders.load(Ordering::SeqCst) == 0;
+        let value = self.queue.lock().pop_front();
+
+        match value {
+            Some(value) => {
+                self.sender_waiters.wake_one();
+                Ok(value)
+            }
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty)
+        }
+    }
+
+    fn add_sender(&self) {
+        self.senders.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn remove_sender(&self) {
+        if self.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.receiver_waiters.wake_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Queues `value` for the receiver. Fails if the receiver is gone. Must
+    /// not be called from interrupt handlers, since it may allocate.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = value;
+
+        loop {
+            match self.channel.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(returned)) => value = returned,
+                Err(TrySendError::Disconnected(returned)) => return Err(SendError(returned))
+            }
+
+            self.channel.grow();
+        }
+    }
+}
+
+impl<T> SyncSender<T> {
+    /// Queues `value` for the receiver, blocking while the channel is full.
+    /// Fails if the receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        let mut result = Ok(());
+
+        self.channel.sender_waiters.wait_until(|| {
+            let message = value.take().expect("Message already sent!");
+
+            match self.channel.try_push(message) {
+                Ok(()) => true,
+                Err(TrySendError::Full(returned)) => {
+                    value = Some(returned);
+                    false
+                }
+                Err(TrySendError::Disconnected(returned)) => {
+                    result = Err(SendError(returned));
+                    true
+                }
+            }
+        });
+
+        result
+    }
+
+    /// Queues `value` if there is room. This never blocks, so it can be used
+    /// from interrupt handlers.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_push(value)
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message arrives. Fails once all senders are gone and
+    /// every message has been received.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut result = Err(RecvError);
+
+        self.channel.receiver_waiters.wait_until(|| {
+            match self.channel.try_pop() {
+                Ok(value) => {
+                    result = Ok(value);
+                    true
+                }
+                Err(TryRecvError::Disconnected) => true,
+                Err(TryRecvError::Empty) => false
+            }
+        });
+
+        result
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_pop()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.channel.add_sender();
+        Sender { channel: self.channel.clone() }
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> SyncSender<T> {
+        self.channel.add_sender();
+        SyncSender { channel: self.channel.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.channel.remove_sender();
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.channel.remove_sender();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.receiver_alive.store(false, Ordering::SeqCst);
+        self.channel.sender_waiters.wake_all();
+    }
+}
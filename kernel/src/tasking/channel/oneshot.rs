@@ -0,0 +1,97 @@
+//! Channels that carry a single message, for example the result of a request
+//! handed to another task.
+
+use alloc::arc::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use tasking::WaitQueue;
+use tasking::sync::IrqMutex;
+use super::{RecvError, SendError, TryRecvError};
+
+struct Oneshot<T> {
+    value: IrqMutex<Option<T>>,
+    /// Set once the sender has sent its message or was dropped
+    sender_done: AtomicBool,
+    receiver_alive: AtomicBool,
+    waiters: WaitQueue
+}
+
+pub struct Sender<T> {
+    oneshot: Arc<Oneshot<T>>
+}
+
+pub struct Receiver<T> {
+    oneshot: Arc<Oneshot<T>>
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let oneshot = Arc::new(Oneshot {
+        value: IrqMutex::new(None),
+        sender_done: AtomicBool::new(false),
+        receiver_alive: AtomicBool::new(true),
+        waiters: WaitQueue::new()
+    });
+
+    (Sender { oneshot: oneshot.clone() }, Receiver { oneshot: oneshot })
+}
+
+impl<T> Sender<T> {
+    /// Hands `value` to the receiver. This never blocks or allocates, so it
+    /// can be used from interrupt handlers.
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let mut slot = self.oneshot.value.lock();
+
+        if !self.oneshot.receiver_alive.load(Ordering::SeqCst) {
+            return Err(SendError(value));
+        }
+
+        *slot = Some(value);
+
+        // Dropping `self` afterwards wakes the receiver up
+        Ok(())
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until the message arrives. Fails if the sender was dropped
+    /// without sending anything.
+    pub fn recv(self) -> Result<T, RecvError> {
+        let mut result = Err(RecvError);
+
+        self.oneshot.waiters.wait_until(|| {
+            match self.try_recv() {
+                Ok(value) => {
+                    result = Ok(value);
+                    true
+                }
+                Err(TryRecvError::Disconnected) => true,
+                Err(TryRecvError::Empty) => false
+            }
+        });
+
+        result
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let done = self.oneshot.sender_done.load(Ordering::SeqCst);
+        let value = self.oneshot.value.lock().take();
+
+        match value {
+            Some(value) => Ok(value),
+            None if done => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.oneshot.sender_done.store(true, Ordering::SeqCst);
+        self.oneshot.waiters.wake_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.oneshot.receiver_alive.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,121 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use tasking::sync::IrqMutex;
+use super::{Future, Poll, Waker};
+
+/// Something that happens over and over, like an interrupt, that a future can
+/// wait for. Signalling it never blocks or allocates, so that can be done from
+/// interrupt handlers.
+///
+/// Only one future can wait for an event at a time: whichever polled last is
+/// woken up. A future that registers its waker has to deregister it once it
+/// completes or is dropped, so the event doesn't keep the executor alive.
+pub struct Event {
+    /// How often the event was signalled
+    count: AtomicUsize,
+    waker: IrqMutex<Option<Waker>>
+}
+
+/// Completes the next time its `Event` is signalled
+pub struct WaitForEvent<'a> {
+    event: &'a Event,
+    start_count: usize,
+    /// The waker we registered with the event, if any
+    registered: Option<Waker>
+}
+
+impl Event {
+    pub const fn new() -> Event {
+        Event { count: AtomicUsize::new(0), waker: IrqMutex::new(None) }
+    }
+
+    /// Wakes up the future waiting for the event, if any. The waker stays
+    /// registered, since it might be the last reference to it, and it must
+    /// not be freed from an interrupt handler. The future takes it out again
+    /// with `deregister`, in task context.
+    pub fn signal(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(ref waker) = *self.waker.lock() {
+            waker.wake();
+        }
+    }
+
+    /// Returns a future that completes the next time the event is signalled.
+    pub fn wait(&self) -> WaitForEvent {
+        WaitForEvent {
+            event: self,
+            start_count: self.count.load(Ordering::SeqCst),
+            registered: None
+        }
+    }
+
+    /// Makes `waker` the one woken up by the next `signal`. This is for
+    /// futures waiting for some condition the event is signalled for, which
+    /// need to check the condition again after registering.
+    pub fn register(&self, waker: &Waker) {
+        let mut current = self.waker.lock();
+
+        match *current {
+            Some(ref registered) if registered.will_wake(waker) => return,
+            _ => {}
+        }
+
+        // The old waker is dropped with interrupts enabled again, in case
+        // this is the last reference to it
+        let old = current.take();
+        *current = Some(waker.clone());
+        drop(current);
+        drop(old);
+    }
+
+    /// Removes `waker`, unless another future has registered since.
+    pub fn deregister(&self, waker: &Waker) {
+        let mut current = self.waker.lock();
+
+        let ours = match *current {
+            Some(ref registered) => registered.will_wake(waker),
+            None => false
+        };
+        let old = if ours { current.take() } else { None };
+
+        // Dropped with interrupts enabled again, like in `register`
+        drop(current);
+        drop(old);
+    }
+}
+
+impl<'a> Future for WaitForEvent<'a> {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> Poll<()> {
+        if self.event.count.load(Ordering::SeqCst) != self.start_count {
+            self.deregister();
+            return Poll::Ready(());
+        }
+
+        self.event.register(waker);
+        self.registered = Some(waker.clone());
+
+        // The event might have been signalled before the waker was registered
+        if self.event.count.load(Ordering::SeqCst) != self.start_count {
+            self.deregister();
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a> WaitForEvent<'a> {
+    fn deregister(&mut self) {
+        if let Some(waker) = self.registered.take() {
+            self.event.deregister(&waker);
+        }
+    }
+}
+
+impl<'a> Drop for WaitForEvent<'a> {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
@@ -0,0 +1,183 @@
+//! A small executor for `Future`-based, event-driven kernel code, running on
+//! top of the regular tasks.
+//!
+//! The toolchain the kernel is built with predates `core::future` and
+//! `async fn`, so this module brings its own `Future` trait, modelled after
+//! the one in the standard library. Futures are written by hand or with
+//! `poll_fn`, and are driven either by an `Executor` running in a task, or by
+//! `block_on` in the current task. Wakers can be used from interrupt
+//! handlers, and `Event` lets futures wait for interrupts.
+//!
+//! Driver logic can't be written as `async fn` until the kernel moves to a
+//! compiler that supports it. Until then, it has to be a hand written state
+//! machine implementing `Future`, like `keyboard::NextEvent`.
+
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+use alloc::Vec;
+use spin::Mutex;
+use tasking::WaitQueue;
+
+pub use self::event::{Event, WaitForEvent};
+pub use self::waker::Waker;
+
+mod event;
+mod waker;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll<T> {
+    Ready(T),
+    /// Not done yet. The future has arranged for the waker to be woken up
+    /// once it can make progress.
+    Pending
+}
+
+impl<T> Poll<T> {
+    pub fn is_ready(&self) -> bool {
+        match *self {
+            Poll::Ready(_) => true,
+            Poll::Pending => false
+        }
+    }
+}
+
+/// A computation that completes at some point in the future.
+pub trait Future {
+    type Output;
+
+    /// Makes as much progress as possible without blocking. If the future
+    /// isn't done, it must arrange for `waker` to be woken up once polling it
+    /// again is worthwhile. Futures must not be polled after they completed.
+    fn poll(&mut self, waker: &Waker) -> Poll<Self::Output>;
+}
+
+impl<F: Future + ?Sized> Future for Box<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<F::Output> {
+        (**self).poll(waker)
+    }
+}
+
+/// A future that calls a closure to poll it
+pub struct PollFn<F> {
+    f: F
+}
+
+/// Creates a future out of a closure. This makes it easy to write futures
+/// without declaring a type for them.
+pub fn poll_fn<T, F>(f: F) -> PollFn<F> where F: FnMut(&Waker) -> Poll<T> {
+    PollFn { f: f }
+}
+
+impl<T, F> Future for PollFn<F> where F: FnMut(&Waker) -> Poll<T> {
+    type Output = T;
+
+    fn poll(&mut self, waker: &Waker) -> Poll<T> {
+        (self.f)(waker)
+    }
+}
+
+type BoxFuture = Box<Future<Output = ()> + Send>;
+
+struct Spawned {
+    future: BoxFuture,
+    waker: Waker
+}
+
+/// Runs many futures concurrently in a single task.
+pub struct Executor {
+    /// The task running the executor waits here for wakeups
+    queue: Arc<WaitQueue>,
+    /// Futures spawned since the executor last looked
+    incoming: Arc<Mutex<Vec<BoxFuture>>>
+}
+
+/// Adds futures to an `Executor`, also from inside futures it is running.
+#[derive(Clone)]
+pub struct Spawner {
+    queue: Arc<WaitQueue>,
+    incoming: Arc<Mutex<Vec<BoxFuture>>>
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor {
+            queue: Arc::new(WaitQueue::new()),
+            incoming: Arc::new(Mutex::new(Vec::new()))
+        }
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        Spawner { queue: self.queue.clone(), incoming: self.incoming.clone() }
+    }
+
+    pub fn spawn<F>(&self, future: F) where F: Future<Output = ()> + Send + 'static {
+        self.spawner().spawn(future);
+    }
+
+    /// Polls the spawned futures whenever they are woken up, until all of
+    /// them have completed. The current task blocks while none can make
+    /// progress.
+    pub fn run(&self) {
+        let mut futures: Vec<Spawned> = Vec::new();
+
+        loop {
+            {
+                let mut incoming = self.incoming.lock();
+                for future in incoming.drain(..) {
+                    futures.push(Spawned { future: future, waker: Waker::new(self.queue.clone()) });
+                }
+            }
+
+            if futures.is_empty() {
+                return;
+            }
+
+            let mut index = 0;
+            while index < futures.len() {
+                let ready = {
+                    let spawned = &mut futures[index];
+                    spawned.waker.take_wakeup() && spawned.future.poll(&spawned.waker).is_ready()
+                };
+
+                if ready {
+                    futures.swap_remove(index);
+                } else {
+                    index += 1;
+                }
+            }
+
+            self.queue.wait_until(|| {
+                futures.iter().any(|spawned| spawned.waker.is_woken()) ||
+                    !self.incoming.lock().is_empty()
+            });
+        }
+    }
+}
+
+impl Spawner {
+    /// Adds `future` to the executor. Must not be called from interrupt
+    /// handlers, since it allocates.
+    pub fn spawn<F>(&self, future: F) where F: Future<Output = ()> + Send + 'static {
+        self.incoming.lock().push(Box::new(future));
+        self.queue.wake_all();
+    }
+}
+
+/// Runs `future` to completion in the current task, blocking while it can't
+/// make progress.
+pub fn block_on<F: Future>(mut future: F) -> F::Output {
+    let queue = Arc::new(WaitQueue::new());
+    let waker = Waker::new(queue.clone());
+
+    loop {
+        if waker.take_wakeup() {
+            if let Poll::Ready(output) = future.poll(&waker) {
+                return output;
+            }
+        }
+
+        queue.wait_until(|| waker.is_woken());
+    }
+}
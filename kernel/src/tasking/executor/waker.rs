@@ -0,0 +1,45 @@
+use alloc::arc::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use tasking::WaitQueue;
+
+/// Tells an executor that a future is worth polling again. Waking never
+/// blocks or allocates, so it can be done from interrupt handlers.
+#[derive(Clone)]
+pub struct Waker {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    woken: AtomicBool,
+    /// The executor waits here
+    queue: Arc<WaitQueue>
+}
+
+impl Waker {
+    /// Creates a waker that starts out woken, so the future gets polled once
+    /// right away.
+    pub(super) fn new(queue: Arc<WaitQueue>) -> Waker {
+        Waker {
+            inner: Arc::new(Inner { woken: AtomicBool::new(true), queue: queue })
+        }
+    }
+
+    pub fn wake(&self) {
+        self.inner.woken.store(true, Ordering::SeqCst);
+        self.inner.queue.wake_all();
+    }
+
+    /// Whether `self` and `other` wake up the same future
+    pub fn will_wake(&self, other: &Waker) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub(super) fn is_woken(&self) -> bool {
+        self.inner.woken.load(Ordering::SeqCst)
+    }
+
+    /// Clears the wakeup, returning whether there was one
+    pub(super) fn take_wakeup(&self) -> bool {
+        self.inner.woken.swap(false, Ordering::SeqCst)
+    }
+}
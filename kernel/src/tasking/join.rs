@@ -0,0 +1,32 @@
+use alloc::arc::Arc;
+use spin::Mutex;
+use super::{tasks_mut, wait_for_exit, current_task_id, TaskId};
+
+/// Owned permission to wait for a task spawned with `spawn` and take its
+/// return value.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    result: Arc<Mutex<Option<T>>>
+}
+
+impl<T> JoinHandle<T> {
+    pub fn new(id: TaskId, result: Arc<Mutex<Option<T>>>) -> JoinHandle<T> {
+        JoinHandle { id: id, result: result }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Blocks until the task ends and returns the value its main function
+    /// returned, or `None` if it ended through `exit` instead.
+    pub fn join(self) -> Option<T> {
+        assert!(self.id != current_task_id(), "A task can't join itself!");
+
+        wait_for_exit(self.id);
+        tasks_mut().collect(self.id);
+
+        let result = self.result.lock().take();
+        result
+    }
+}
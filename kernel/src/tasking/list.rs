@@ -1,19 +1,50 @@
 use alloc::arc::Arc;
-use alloc::BTreeMap;
-use core::mem;
+use alloc::{BTreeMap, String, Vec};
+use alloc::btree_map::Range;
+use arch::memory::{self, PAGE_SIZE};
+use arch::pit;
+use core::collections::Bound::{Excluded, Unbounded};
+use core::iter::Chain;
+use core::{mem, ptr};
 use spin::RwLock;
-use super::{Task, TaskId, TaskMain, current_task_id, MAX_TASKS};
-use super::task::execute_task;
+use super::{ExitStatus, Task, TaskId, TaskMain, current_task_id, scheduler, MAX_TASKS};
+use super::task::{execute_task, STACK_PAINT};
+use time::Duration;
 
 pub struct TaskList {
     tasks: BTreeMap<TaskId, Arc<RwLock<Task>>>,
+    /// Parent and exit status of reaped tasks that haven't been waited for yet
+    exit_statuses: BTreeMap<TaskId, (TaskId, ExitStatus)>,
+    /// Runs when nothing else can. The scheduler never sees it.
+    idle_task: Option<TaskId>,
     next_id: usize
 }
 
+/// Iterator returned by `TaskList::iter_after`
+pub struct IterAfter<'a> {
+    tasks: Chain<Range<'a, TaskId, Arc<RwLock<Task>>>, Range<'a, TaskId, Arc<RwLock<Task>>>>,
+    idle_task: Option<TaskId>
+}
+
+impl<'a> Iterator for IterAfter<'a> {
+    type Item = (&'a TaskId, &'a Arc<RwLock<Task>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.tasks.next() {
+                Some((id, _)) if Some(*id) == self.idle_task => continue,
+                item => return item
+            }
+        }
+    }
+}
+
 impl TaskList {
     pub fn new() -> Self {
        TaskList {
            tasks: BTreeMap::new(),
+           exit_statuses: BTreeMap::new(),
+           idle_task: None,
            next_id: 0
        }
     }
@@ -30,42 +61,49 @@ impl TaskList {
         return self.get(current_task_id())
     }
 
-    pub fn next(&self) -> Option<&Arc<RwLock<Task>>> {
-        let current_id = current_task_id();
+    /// The CPU time used so far by the task `id`
+    pub fn cpu_time(&self, id: TaskId) -> Option<Duration> {
+        self.get(id).map(|task_lock| task_lock.read().cpu_time())
+    }
 
-        let can_run = |task: &mut Task| -> bool {
-            !task.finished
-        };
+    /// Iterates over all tasks except `id` and the idle task, starting with
+    /// the ones after `id`
+    pub fn iter_after(&self, id: TaskId) -> IterAfter {
+        IterAfter {
+            tasks: self.tasks.range((Excluded(id), Unbounded)).chain(self.tasks.range(..id)),
+            idle_task: self.idle_task
+        }
+    }
 
-        for (id, task_lock) in self.iter() {
-            if *id > current_id {
-                let mut task = task_lock.write();
+    pub fn set_idle_task(&mut self, id: TaskId) {
+        self.idle_task = Some(id);
+    }
 
-                if can_run(&mut task) {
-                    return Some(task_lock);
-                }
-            }
-        }
+    pub fn is_idle_task(&self, id: TaskId) -> bool {
+        self.idle_task == Some(id)
+    }
 
-        for (id, task_lock) in self.iter() {
-            if *id < current_id {
-                let mut task = task_lock.write();
+    /// Asks the scheduler for the task to run after `current`, falling back
+    /// on the idle task if neither can run. This never blocks, so it can be
+    /// used from the timer interrupt.
+    pub fn next(&self, current: &Task, preempted: bool) -> Option<&Arc<RwLock<Task>>> {
+        let now = pit::ticks();
 
-                if can_run(&mut task) {
-                    return Some(task_lock);
-                }
-            }
+        match scheduler().and_then(|scheduler| scheduler.next(self, current, now, preempted)) {
+            Some(next) => Some(next),
+            None if current.state.is_runnable(now) || self.is_idle_task(current.id) => None,
+            None => self.idle_task.and_then(|id| self.get(id))
         }
-
-        None
     }
 
-    pub fn new_task(&mut self, main: TaskMain) -> Result<&Arc<RwLock<Task>>, &str> {
+    pub fn new_task(&mut self, name: String, main: TaskMain) -> Result<&Arc<RwLock<Task>>, &str> {
         if self.next_id > MAX_TASKS {
             self.next_id = 0;
         }
 
-        while self.tasks.contains_key(&TaskId::from(self.next_id)) {
+        // IDs with an uncollected exit status are still taken
+        while self.tasks.contains_key(&TaskId::from(self.next_id)) ||
+                self.exit_statuses.contains_key(&TaskId::from(self.next_id)) {
             self.next_id += 1;
         }
 
@@ -76,34 +114,130 @@ impl TaskList {
         let id = TaskId::from(self.next_id);
         self.next_id += 1;
 
-        assert!(self.tasks.insert(id, Arc::new(RwLock::new(Task::new(id, main)))).is_none());
+        let mut task = Task::new(id, name, main);
+
+        if let Some(current_lock) = self.current() {
+            let current = current_lock.read();
+
+            task.parent = Some(current.id);
+            // Start out even with the parent, so the fair scheduler neither
+            // starves the new task nor lets it take over the CPU
+            task.vruntime = current.vruntime;
+        }
+
+        assert!(self.tasks.insert(id, Arc::new(RwLock::new(task))).is_none());
 
         Ok(self.tasks.get(&id).expect("Unable to create new task. ID was invalid."))
     }
 
-    pub fn spawn(&mut self, main: TaskMain) -> Result<&Arc<RwLock<Task>>, &str> {
+    /// Creates a task running `main` on a new stack of at least `stack_size`
+    /// bytes, with an unmapped guard page below it to catch overflows.
+    pub fn spawn(&mut self, name: String, main: TaskMain, stack_size: usize)
+            -> Result<&Arc<RwLock<Task>>, &str> {
         use x86::shared::control_regs;
+        use x86::shared::flags::{flags, FLAGS_IF};
+
+        let stack = memory::controller().alloc_stack((stack_size + PAGE_SIZE - 1) / PAGE_SIZE)
+            .ok_or("Unable to allocate a stack for the new task!")?;
 
-        let task_lock = self.new_task(main)?;
+        let task_lock = match self.new_task(name, main) {
+            Ok(task_lock) => task_lock,
+            Err(error) => {
+                memory::controller().free_stack(stack);
+                return Err(error);
+            }
+        };
         let mut task = task_lock.write();
 
-        let mut stack = vec![0; 65536].into_boxed_slice();
-        let offset = stack.len() - mem::size_of::<usize>();
+        let offset = stack.top() - mem::size_of::<usize>();
         unsafe {
-            let func_ptr = stack.as_mut_ptr().offset(offset as isize);
-            *(func_ptr as *mut usize) = execute_task as usize;
+            ptr::write_bytes(stack.bottom() as *mut u8, STACK_PAINT, stack.top() - stack.bottom());
+            *(offset as *mut usize) = execute_task as usize;
         }
         task.context.set_page_table(unsafe { control_regs::cr3() });
-        task.context.set_stack(stack.as_ptr() as usize + offset);
+        task.context.set_stack(offset);
 
-        unsafe {
-            let rflags: usize;
-            asm!("pushfq; mov $0, [rsp]; popfq" : "=r"(rflags) : : "memory" : "intel", "volatile");
-            task.context.set_rflags(rflags);
-        }
+        // New tasks always start with interrupts enabled, so that they can be
+        // preempted even if they were spawned from a critical section
+        task.context.set_rflags((flags() | FLAGS_IF).bits());
 
         task.kernel_stack = Some(stack);
 
         Ok(task_lock)
     }
+
+    /// Removes finished tasks, which frees their stacks once nothing else
+    /// holds on to them. The current task is skipped, since it is still
+    /// running on its stack until it switches away. Exit statuses are kept
+    /// around for `take_exit_status` as long as the parent is alive.
+    pub fn reap(&mut self) {
+        let current_id = current_task_id();
+
+        let finished: Vec<TaskId> = self.tasks.iter()
+            .filter(|&(id, task_lock)| {
+                *id != current_id &&
+                    task_lock.try_read().map_or(false, |task| task.is_finished())
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in finished {
+            let task_lock = self.tasks.remove(&id).expect("Finished task disappeared!");
+            let task = task_lock.read();
+
+            if let Some(parent) = task.parent {
+                if self.tasks.contains_key(&parent) {
+                    self.exit_statuses.insert(id, (parent, task.exit_status));
+                }
+            }
+        }
+
+        // Nobody is left to collect these
+        let orphaned: Vec<TaskId> = self.exit_statuses.iter()
+            .filter(|&(_, &(parent, _))| !self.tasks.contains_key(&parent))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in orphaned {
+            self.exit_statuses.remove(&id);
+        }
+    }
+
+    /// Returns the exit status of the finished task `id`, if `parent` spawned
+    /// it. `Ok(None)` means the task is still running.
+    pub fn take_exit_status(&mut self, id: TaskId, parent: TaskId)
+            -> Result<Option<ExitStatus>, &'static str> {
+        match self.exit_statuses.get(&id) {
+            Some(&(task_parent, _)) if task_parent != parent => {
+                return Err("Task is not a child of the waiting task");
+            }
+            Some(_) => {}
+            None => {
+                match self.tasks.get(&id) {
+                    Some(task_lock) if task_lock.read().parent == Some(parent) => {}
+                    Some(_) => return Err("Task is not a child of the waiting task"),
+                    None => return Err("No such task")
+                }
+            }
+        }
+
+        Ok(self.collect(id))
+    }
+
+    /// Removes the finished task `id` without waiting for the reaper, along
+    /// with any exit status it left behind.
+    pub fn collect(&mut self, id: TaskId) -> Option<ExitStatus> {
+        let finished = match self.tasks.get(&id) {
+            Some(task_lock) => id != current_task_id() && task_lock.read().is_finished(),
+            None => false
+        };
+
+        if finished {
+            let task_lock = self.tasks.remove(&id).expect("Finished task disappeared!");
+            let status = task_lock.read().exit_status;
+            Some(status)
+        } else {
+            self.exit_statuses.remove(&id).map(|(_, status)| status)
+        }
+    }
 }
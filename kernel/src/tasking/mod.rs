@@ -1,27 +1,80 @@
-use core::sync::atomic::Ordering;
+use alloc::arc::Arc;
+use alloc::boxed::Box;
+use alloc::{String, Vec};
+use arch::{interrupts, pit};
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use self::list::TaskList;
-use spin::{Once, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use self::task::SharedState;
+use spin::{Mutex, Once, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use time::Duration;
 
-pub use self::task::{Task, TaskId, TaskMain};
+pub use self::join::JoinHandle;
+pub use self::scheduler::{Scheduler, RoundRobin, FixedPriority, Fair};
+pub use self::task::{ExitStatus, Task, TaskId, TaskInfo, TaskMain, TaskState};
+pub use self::wait_queue::WaitQueue;
 pub use self::switching::switch;
+pub use self::preempt::{PreemptGuard, disable_preemption, preemption_enabled, set_time_slice,
+                        tick};
 
+pub mod channel;
+pub mod executor;
+pub mod sync;
+#[cfg(feature = "self_test")]
+pub mod self_test;
+
+mod join;
 mod list;
+mod preempt;
+mod scheduler;
 mod switching;
 mod task;
+mod wait_queue;
 
 pub const MAX_TASKS: usize = usize::max_value() - 1;
 
+/// Stack size of tasks spawned with `spawn`
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024;
+
+/// Higher priority tasks are preferred by the scheduler
+pub type Priority = u8;
+
+pub const MIN_PRIORITY: Priority = 0;
+pub const DEFAULT_PRIORITY: Priority = 127;
+pub const MAX_PRIORITY: Priority = 255;
+
 static TASKS: Once<RwLock<TaskList>> = Once::new();
 
+static SCHEDULER: Once<Box<Scheduler>> = Once::new();
+
 static CURRENT_TASK_ID: task::AtomicTaskId = task::AtomicTaskId::default();
 
-pub fn init() {
+/// Woken up whenever a task finishes
+static REAPER_QUEUE: Once<WaitQueue> = Once::new();
+/// Finished tasks the reaper hasn't seen yet
+static UNREAPED_TASKS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Sets up tasking, with `scheduler` deciding which task runs when.
+pub fn init(scheduler: Box<Scheduler>) {
+    assert_has_not_been_called!("tasking::init must be called only once");
+
+    SCHEDULER.call_once(|| scheduler);
+
     let mut tasks = tasks_mut();
-    let task_lock = tasks.new_task(::kernel_main)
-        .expect("Unable to initialize the primary kernel task!");
-    let task = task_lock.write();
+    let id = tasks.new_task(String::from("kernel_main"), Box::new(::kernel_main))
+        .expect("Unable to initialize the primary kernel task!")
+        .read().id;
+
+    CURRENT_TASK_ID.store(id, Ordering::SeqCst);
+
+    REAPER_QUEUE.call_once(WaitQueue::new);
+    tasks.spawn(String::from("reaper"), Box::new(reaper), DEFAULT_STACK_SIZE)
+        .expect("Unable to spawn the reaper task!");
 
-    CURRENT_TASK_ID.store(task.id, Ordering::SeqCst);
+    let idle_id = tasks.spawn(String::from("idle"), Box::new(idle), DEFAULT_STACK_SIZE)
+        .expect("Unable to spawn the idle task!")
+        .read().id;
+    tasks.set_idle_task(idle_id);
 
     ok!("Tasking initialized.");
 }
@@ -42,20 +95,241 @@ pub fn current_task_id() -> TaskId {
     CURRENT_TASK_ID.load(Ordering::SeqCst)
 }
 
-pub fn spawn(main: TaskMain) {
+fn scheduler() -> Option<&'static Scheduler> {
+    SCHEDULER.try().map(|scheduler| &**scheduler)
+}
+
+/// Changes the priority of the task `id`. The scheduler picks it up the next
+/// time it chooses a task.
+pub fn set_priority(id: TaskId, priority: Priority) -> Result<(), &'static str> {
+    let tasks = tasks();
+    let task_lock = tasks.get(id).ok_or("No such task")?;
+
+    task_lock.write().priority = priority;
+    Ok(())
+}
+
+/// Runs `f` in a new task. Its return value can be collected with the
+/// returned `JoinHandle`.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    spawn_task(String::new(), f, DEFAULT_STACK_SIZE)
+}
+
+/// Like `spawn`, but gives the task a name to show up with in `snapshot`
+pub fn spawn_named<F, T>(name: &str, f: F) -> JoinHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    spawn_task(String::from(name), f, DEFAULT_STACK_SIZE)
+}
+
+/// Spawns a task with a stack of at least `stack_size` bytes
+pub fn spawn_with_stack_size<F, T>(f: F, stack_size: usize) -> JoinHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    spawn_task(String::new(), f, stack_size)
+}
+
+fn spawn_task<F, T>(name: String, f: F, stack_size: usize) -> JoinHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static {
+    let result = Arc::new(Mutex::new(None));
+    let task_result = result.clone();
+
+    let mut f = Some(f);
+    let main: TaskMain = Box::new(move || {
+        let f = f.take().expect("Task main function called twice!");
+        *task_result.lock() = Some(f());
+    });
+
     let mut tasks = tasks_mut();
+    let id = tasks.spawn(name, main, stack_size).expect("Unable to spawn new kernel task!!")
+        .read().id;
+
+    JoinHandle::new(id, result)
+}
+
+/// Returns a record of every task, for printing `ps`-style listings. Tasks
+/// that are locked at the moment are left out rather than waited for, so this
+/// is also usable while debugging a hang.
+pub fn snapshot() -> Vec<TaskInfo> {
+    let tasks = match TASKS.try().and_then(|tasks| tasks.try_read()) {
+        Some(tasks) => tasks,
+        None => return Vec::new()
+    };
 
-    tasks.spawn(main).expect("Unable to spawn new kernel task!!");
+    tasks.iter()
+        .filter_map(|(_, task_lock)| task_lock.try_read().map(|task| task.info()))
+        .collect()
 }
 
-pub fn exit() -> ! {
+/// Prints a table of all tasks.
+pub fn dump() {
+    info!("   ID  PARENT  STATE     PRIO    CREATED        CPU       STACK  NAME");
+
+    for task in snapshot() {
+        info!("{}", task);
+    }
+}
+
+/// Returns the task whose stack guard page contains `address`, if any. This
+/// is meant for the page fault handler, so it gives up instead of blocking on
+/// a lock.
+pub fn stack_overflow_task(address: usize) -> Option<TaskId> {
+    let tasks = match TASKS.try().and_then(|tasks| tasks.try_read()) {
+        Some(tasks) => tasks,
+        None => return None
+    };
+
+    for (id, task_lock) in tasks.iter() {
+        if let Some(task) = task_lock.try_read() {
+            match task.kernel_stack {
+                Some(ref stack) if stack.guard_page_contains(address) => return Some(*id),
+                _ => {}
+            }
+        }
+    }
+
+    None
+}
+
+/// Ends the current task. Its resources are freed by the reaper, and the
+/// parent task can collect `status` with `wait`.
+pub fn exit(status: ExitStatus) -> ! {
     {
-        let tasks = tasks();
-        let current_lock = tasks.current()
-                .expect("Attempting to switch tasks without a task running!");
-        let mut current = current_lock.write();
-        current.finished = true;
+        let (state, exit_queue) = {
+            let tasks = tasks();
+            let current_lock = tasks.current()
+                    .expect("Attempting to switch tasks without a task running!");
+            let mut current = current_lock.write();
+            current.exit_status = status;
+
+            (current.state.clone(), current.exit_queue.clone())
+        };
+
+        // A finished task is never switched back to, so it can't be preempted
+        // before it has woken up the tasks waiting for it
+        let _guard = disable_preemption();
+
+        state.set(TaskState::Finished);
+        exit_queue.wake_all();
+
+        UNREAPED_TASKS.fetch_add(1, Ordering::SeqCst);
+        if let Some(queue) = REAPER_QUEUE.try() {
+            queue.wake_all();
+        }
     }
     switch();
     panic!("Returned to a dead task!");
 }
+
+/// Waits for the child task `id` to exit and returns its exit status. A
+/// status can only be collected once.
+pub fn wait(id: TaskId) -> Result<ExitStatus, &'static str> {
+    loop {
+        if let Some(status) = tasks_mut().take_exit_status(id, current_task_id())? {
+            return Ok(status);
+        }
+
+        wait_for_exit(id);
+    }
+}
+
+/// Puts the current task to sleep for at least `duration`.
+pub fn sleep(duration: Duration) {
+    let wake_tick = pit::ticks().saturating_add(pit::duration_to_ticks(duration));
+
+    let (_, state) = current_state();
+
+    while pit::ticks() < wake_tick {
+        state.sleep_until(wake_tick);
+        switch();
+    }
+
+    // We might not have switched away at all if nothing else could run
+    state.set(TaskState::Runnable);
+}
+
+/// Blocks until the task `id` has finished.
+fn wait_for_exit(id: TaskId) {
+    let (state, exit_queue) = {
+        let tasks = tasks();
+
+        match tasks.get(id) {
+            Some(task_lock) => {
+                let task = task_lock.read();
+                (task.state.clone(), task.exit_queue.clone())
+            }
+            None => return
+        }
+    };
+
+    exit_queue.wait_until(|| state.get() == TaskState::Finished);
+}
+
+fn current_state() -> (TaskId, Arc<SharedState>) {
+    let tasks = tasks();
+    let current_lock = tasks.current().expect("No task is running!");
+    let state = current_lock.read().state.clone();
+
+    (current_task_id(), state)
+}
+
+/// Removes finished tasks in the background. This can't be done from the
+/// exiting task itself, since it is still running on the stack to be freed.
+fn reaper() {
+    let queue = REAPER_QUEUE.try().expect("Reaper queue not initialized!");
+
+    loop {
+        queue.wait_until(|| UNREAPED_TASKS.load(Ordering::SeqCst) > 0);
+        UNREAPED_TASKS.store(0, Ordering::SeqCst);
+
+        tasks_mut().reap();
+    }
+}
+
+/// Runs whenever no other task can, and halts the CPU until the next
+/// interrupt. In tickless mode, the timer is stopped until the next sleeping
+/// task or kernel timer is due.
+fn idle() {
+    use x86::shared::irq;
+
+    loop {
+        // Interrupts stay disabled until the CPU is halted, so that a task
+        // woken up by an interrupt after we looked can't be missed
+        unsafe { irq::disable() };
+        switch();
+
+        match next_wake_tick() {
+            Some(tick) => pit::skip_ticks(tick.saturating_sub(pit::ticks())),
+            None => pit::skip_ticks(usize::max_value())
+        }
+
+        interrupts::enable_and_halt();
+        interrupts::without_interrupts(pit::resume_ticks);
+    }
+}
+
+/// The earliest tick a sleeping task or kernel timer is due at. Returns the
+/// next tick if the tasks can't be checked without blocking.
+fn next_wake_tick() -> Option<usize> {
+    let now = pit::ticks();
+    let tasks = match TASKS.try().and_then(|tasks| tasks.try_read()) {
+        Some(tasks) => tasks,
+        None => return Some(now + 1)
+    };
+
+    let mut next = ::timer::next_deadline();
+
+    for (_, task_lock) in tasks.iter() {
+        let wake_tick = match task_lock.try_read() {
+            Some(task) => task.state.wake_tick(),
+            None => return Some(now + 1)
+        };
+
+        next = match (next, wake_tick) {
+            (Some(next), Some(tick)) => Some(cmp::min(next, tick)),
+            (next, None) => next,
+            (None, tick) => tick
+        };
+    }
+
+    next
+}
@@ -0,0 +1,71 @@
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use super::switching;
+
+/// Number of timer ticks a task may run before it is preempted
+pub const DEFAULT_TIME_SLICE: usize = 10;
+
+/// Nesting depth of `disable_preemption` for the running task. This is saved
+/// and restored on every task switch.
+static PREEMPT_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+static TIME_SLICE: AtomicUsize = AtomicUsize::new(DEFAULT_TIME_SLICE);
+static SLICE_REMAINING: AtomicUsize = AtomicUsize::new(DEFAULT_TIME_SLICE);
+
+/// Keeps the timer from switching away from the current task until dropped.
+/// Guards can be nested.
+pub struct PreemptGuard {
+    _private: ()
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        PREEMPT_COUNT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn disable_preemption() -> PreemptGuard {
+    PREEMPT_COUNT.fetch_add(1, Ordering::SeqCst);
+    PreemptGuard { _private: () }
+}
+
+pub fn preemption_enabled() -> bool {
+    PREEMPT_COUNT.load(Ordering::SeqCst) == 0
+}
+
+pub fn preempt_count() -> usize {
+    PREEMPT_COUNT.load(Ordering::SeqCst)
+}
+
+pub fn set_preempt_count(count: usize) {
+    PREEMPT_COUNT.store(count, Ordering::SeqCst);
+}
+
+/// Sets the number of timer ticks a task may run before it is preempted.
+pub fn set_time_slice(ticks: usize) {
+    assert!(ticks > 0, "The time slice must be at least one tick");
+
+    TIME_SLICE.store(ticks, Ordering::SeqCst);
+}
+
+/// Gives the running task a fresh time slice.
+pub fn reset_time_slice() {
+    SLICE_REMAINING.store(TIME_SLICE.load(Ordering::SeqCst), Ordering::SeqCst);
+}
+
+/// Called from the timer interrupt with the number of ticks since the last
+/// call, which is more than one after tickless idling.
+pub fn tick(ticks: usize) {
+    let remaining = SLICE_REMAINING.load(Ordering::SeqCst);
+
+    if remaining > ticks {
+        SLICE_REMAINING.store(remaining - ticks, Ordering::SeqCst);
+    } else {
+        // Leave the slice expired if we can't switch right now, so that we
+        // try again on the next tick.
+        SLICE_REMAINING.store(0, Ordering::SeqCst);
+
+        if preemption_enabled() {
+            switching::preempt();
+        }
+    }
+}
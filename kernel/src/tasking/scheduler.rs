@@ -0,0 +1,158 @@
+use alloc::arc::Arc;
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use spin::{RwLock, RwLockWriteGuard};
+use super::{Priority, Task};
+use super::list::TaskList;
+use super::switching;
+
+/// Lowest `vruntime` among the runnable tasks the last time the fair scheduler
+/// looked. It never decreases.
+static MIN_VRUNTIME: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Decides which task runs next. This is called from the timer interrupt, so
+/// implementations must not block: tasks whose lock can't be taken right away
+/// are simply skipped.
+pub trait Scheduler: Send + Sync {
+    /// Picks the task to switch to from the tasks other than `current`, or
+    /// returns `None` to keep running the current task. `now` is the current
+    /// timer tick. `preempted` tells whether the current task's time slice ran
+    /// out, rather than it giving up the CPU itself. The caller already holds
+    /// the lock of the current task.
+    fn next<'a>(&self, tasks: &'a TaskList, current: &Task, now: usize, preempted: bool)
+        -> Option<&'a Arc<RwLock<Task>>>;
+}
+
+/// Runs every runnable task in turn, in ID order, ignoring priorities.
+pub struct RoundRobin;
+
+/// Always runs the highest priority runnable task. Tasks of the same priority
+/// take turns.
+pub struct FixedPriority;
+
+/// Runs the task that has had the least CPU time, weighted by priority, so
+/// that every task gets a share of the CPU proportional to its priority. When
+/// its time slice runs out, the current task keeps running as long as it is
+/// not ahead of the others. A task that yields always gives way.
+pub struct Fair;
+
+impl Scheduler for RoundRobin {
+    fn next<'a>(&self, tasks: &'a TaskList, current: &Task, now: usize, _preempted: bool)
+            -> Option<&'a Arc<RwLock<Task>>> {
+        for (_, task_lock) in tasks.iter_after(current.id) {
+            if runnable(task_lock, now).is_some() {
+                return Some(task_lock);
+            }
+        }
+
+        None
+    }
+}
+
+impl Scheduler for FixedPriority {
+    fn next<'a>(&self, tasks: &'a TaskList, current: &Task, now: usize, _preempted: bool)
+            -> Option<&'a Arc<RwLock<Task>>> {
+        let mut best: Option<(Priority, &'a Arc<RwLock<Task>>)> = None;
+
+        for (_, task_lock) in tasks.iter_after(current.id) {
+            let priority = match runnable(task_lock, now) {
+                Some(task) => task.priority,
+                None => continue
+            };
+
+            // Only a strictly higher priority wins, so ties go to the task
+            // closest after the current one
+            match best {
+                Some((best_priority, _)) if best_priority >= priority => {}
+                _ => best = Some((priority, task_lock))
+            }
+        }
+
+        let (best_priority, best_lock) = match best {
+            Some(best) => best,
+            None => return None
+        };
+
+        // Keep running the current task if it is more important
+        if current_runnable(tasks, current, now) && current.priority > best_priority {
+            None
+        } else {
+            Some(best_lock)
+        }
+    }
+}
+
+impl Scheduler for Fair {
+    fn next<'a>(&self, tasks: &'a TaskList, current: &Task, now: usize, preempted: bool)
+            -> Option<&'a Arc<RwLock<Task>>> {
+        let min_vruntime = MIN_VRUNTIME.load(Ordering::SeqCst);
+        let mut best: Option<(usize, &'a Arc<RwLock<Task>>)> = None;
+
+        for (_, task_lock) in tasks.iter_after(current.id) {
+            let vruntime = match runnable(task_lock, now) {
+                Some(mut task) => {
+                    // Tasks that have been asleep or were just created don't
+                    // get to catch up on the time they weren't runnable
+                    task.vruntime = cmp::max(task.vruntime, min_vruntime);
+                    task.vruntime
+                }
+                None => continue
+            };
+
+            match best {
+                Some((best_vruntime, _)) if best_vruntime <= vruntime => {}
+                _ => best = Some((vruntime, task_lock))
+            }
+        }
+
+        // The current task hasn't been charged for its time slice yet
+        let current_vruntime = current.vruntime_after(switching::running_ticks(now));
+        let current_runnable = current_runnable(tasks, current, now);
+
+        let lowest = match (best, current_runnable) {
+            (Some((best_vruntime, _)), true) => Some(cmp::min(best_vruntime, current_vruntime)),
+            (Some((best_vruntime, _)), false) => Some(best_vruntime),
+            (None, true) => Some(current_vruntime),
+            (None, false) => None
+        };
+        if let Some(lowest) = lowest {
+            let mut old = min_vruntime;
+            while old < lowest {
+                let previous = MIN_VRUNTIME.compare_and_swap(old, lowest, Ordering::SeqCst);
+                if previous == old {
+                    break;
+                }
+                old = previous;
+            }
+        }
+
+        // A task that yields wants another task to run, even if it is behind
+        let may_keep = preempted && current_runnable;
+
+        match best {
+            Some((best_vruntime, _)) if may_keep && current_vruntime <= best_vruntime => None,
+            Some((_, task_lock)) => Some(task_lock),
+            None => None
+        }
+    }
+}
+
+/// Whether the current task can go on running. The idle task never competes
+/// with other tasks, so it always gives way.
+fn current_runnable(tasks: &TaskList, current: &Task, now: usize) -> bool {
+    !tasks.is_idle_task(current.id) && current.state.is_runnable(now)
+}
+
+/// Locks the task if it can run now
+fn runnable(task_lock: &Arc<RwLock<Task>>, now: usize) -> Option<RwLockWriteGuard<Task>> {
+    match task_lock.try_write() {
+        Some(task) => {
+            if task.state.is_runnable(now) {
+                Some(task)
+            } else {
+                None
+            }
+        }
+        None => None
+    }
+}
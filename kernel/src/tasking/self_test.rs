@@ -0,0 +1,95 @@
+//! Checks of the tasking primitives, run at boot with the `self_test` feature.
+//! A failed check panics, a lost wakeup shows up as a hang.
+
+use alloc::arc::Arc;
+use alloc::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use super::{spawn_named, set_time_slice, WaitQueue};
+use super::preempt::DEFAULT_TIME_SLICE;
+use super::sync::{Mutex, RwLock, Semaphore};
+
+/// How often each race is run
+const ROUNDS: usize = 100;
+
+/// Tasks fighting over each lock in the stress tests
+const CONTENDERS: usize = 8;
+
+pub fn run() {
+    // Preempt as often as possible, to hit the windows between parking and
+    // switching away
+    set_time_slice(1);
+
+    wakeup_before_wait();
+    contended_locks();
+
+    set_time_slice(DEFAULT_TIME_SLICE);
+    ok!("Tasking self test passed.");
+}
+
+/// The condition is set and the queue woken up before the waiter parks, so
+/// only its own check of the condition can let it through.
+fn wakeup_before_wait() {
+    let queue = WaitQueue::new();
+    let done = AtomicBool::new(false);
+
+    done.store(true, Ordering::SeqCst);
+    queue.wake_all();
+    queue.wait_until(|| done.load(Ordering::SeqCst));
+
+    // The same, but racing against another task. Joining mostly finds the task
+    // already finished.
+    for _ in 0..ROUNDS {
+        let queue = Arc::new(WaitQueue::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let (task_queue, task_done) = (queue.clone(), done.clone());
+
+        let handle = spawn_named("wakeup_test", move || {
+            task_done.store(true, Ordering::SeqCst);
+            task_queue.wake_all();
+        });
+
+        queue.wait_until(|| done.load(Ordering::SeqCst));
+        handle.join();
+    }
+}
+
+/// Every contender takes each lock `ROUNDS` times and checks it is alone
+/// while holding it. A wakeup lost between parking and switching away leaves
+/// a contender blocked forever, so the joins never return.
+fn contended_locks() {
+    let mutex = Arc::new(Mutex::new(0));
+    let rwlock = Arc::new(RwLock::new(0));
+    let semaphore = Arc::new(Semaphore::new(1));
+    let inside = Arc::new(AtomicBool::new(false));
+
+    let handles: Vec<_> = (0..CONTENDERS).map(|_| {
+        let (mutex, rwlock) = (mutex.clone(), rwlock.clone());
+        let (semaphore, inside) = (semaphore.clone(), inside.clone());
+
+        spawn_named("lock_test", move || {
+            for _ in 0..ROUNDS {
+                *mutex.lock() += 1;
+
+                {
+                    let mut value = rwlock.write();
+                    let read = *value;
+                    *value = read + 1;
+                }
+                let _ = *rwlock.read();
+
+                semaphore.acquire();
+                assert!(!inside.swap(true, Ordering::SeqCst), "Semaphore let two tasks in!");
+                inside.store(false, Ordering::SeqCst);
+                semaphore.release();
+            }
+        })
+    }).collect();
+
+    for handle in handles {
+        handle.join();
+    }
+
+    assert_eq!(*mutex.lock(), CONTENDERS * ROUNDS);
+    assert_eq!(*rwlock.read(), CONTENDERS * ROUNDS);
+    assert_eq!(semaphore.available(), 1);
+}
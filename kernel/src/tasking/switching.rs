@@ -1,31 +1,101 @@
-use core::sync::atomic::Ordering;
+use arch::{interrupts, pit};
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use core::ops::DerefMut;
-use super::{tasks, Task, CURRENT_TASK_ID};
+use super::{tasks, Task, CURRENT_TASK_ID, TASKS};
+use super::preempt;
 
+/// Timer tick the current task was switched to
+static RUN_START: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Timer ticks the current task has been running for since it was switched
+/// to, which it hasn't been charged for yet
+pub fn running_ticks(now: usize) -> usize {
+    now.saturating_sub(RUN_START.load(Ordering::SeqCst))
+}
+
+/// Cooperatively yields the CPU to the next runnable task.
 pub fn switch() {
+    // Interrupts stay disabled until we are running on the new task's stack,
+    // so the timer can't try to switch tasks while we are in the middle of it.
+    interrupts::without_interrupts(|| {
+        let from_ptr;
+        let to_ptr;
+
+        {
+            let tasks = tasks();
+
+            let current_lock = tasks.current()
+                .expect("Attempting to switch tasks without a task running!");
+            let mut current = current_lock.write();
+
+            from_ptr = current.deref_mut() as *mut Task;
+
+            if let Some(next_lock) = tasks.next(&current, false) {
+                let mut next = next_lock.write();
+
+                to_ptr = next.deref_mut() as *mut Task;
+            } else {
+                preempt::reset_time_slice();
+                return;
+            }
+        }
+
+        unsafe { switch_to(from_ptr, to_ptr) };
+    })
+}
+
+/// Switches to the next runnable task from the timer interrupt. This must never
+/// block, since the interrupted task might be holding any of the scheduler
+/// locks. If one of them is contended, the switch is simply skipped.
+pub fn preempt() {
     let from_ptr;
     let to_ptr;
 
     {
-        let tasks = tasks();
+        let tasks = match TASKS.try().and_then(|tasks| tasks.try_read()) {
+            Some(tasks) => tasks,
+            None => return
+        };
 
-        let current_lock = tasks.current()
-            .expect("Attempting to switch tasks without a task running!");
-        let mut current = current_lock.write();
+        let current_lock = match tasks.current() {
+            Some(current_lock) => current_lock,
+            None => return
+        };
+        let mut current = match current_lock.try_write() {
+            Some(current) => current,
+            None => return
+        };
 
         from_ptr = current.deref_mut() as *mut Task;
 
-        if let Some(next_lock) = tasks.next() {
-            let mut next = next_lock.write();
+        if let Some(next_lock) = tasks.next(&current, true) {
+            let mut next = match next_lock.try_write() {
+                Some(next) => next,
+                None => return
+            };
 
             to_ptr = next.deref_mut() as *mut Task;
         } else {
+            preempt::reset_time_slice();
             return;
         }
     }
 
-    unsafe {
-        CURRENT_TASK_ID.store((&mut *to_ptr).id, Ordering::SeqCst);
-        (&mut *from_ptr).context.switch_to(&mut (&mut *to_ptr).context);
-    }
+    unsafe { switch_to(from_ptr, to_ptr) };
+}
+
+/// Must be called with interrupts disabled.
+unsafe fn switch_to(from_ptr: *mut Task, to_ptr: *mut Task) {
+    let from = &mut *from_ptr;
+    let to = &mut *to_ptr;
+
+    let now = pit::ticks();
+    from.account(now - RUN_START.swap(now, Ordering::SeqCst));
+
+    from.preempt_count = preempt::preempt_count();
+    preempt::set_preempt_count(to.preempt_count);
+    preempt::reset_time_slice();
+
+    CURRENT_TASK_ID.store(to.id, Ordering::SeqCst);
+    from.context.switch_to(&mut to.context);
 }
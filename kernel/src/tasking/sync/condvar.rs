@@ -0,0 +1,42 @@
+use tasking::WaitQueue;
+use super::MutexGuard;
+
+/// A condition variable, used together with a `Mutex`.
+pub struct Condvar {
+    waiters: WaitQueue
+}
+
+impl Condvar {
+    pub fn new() -> Condvar {
+        Condvar { waiters: WaitQueue::new() }
+    }
+
+    /// Unlocks the mutex, blocks until notified and locks the mutex again.
+    /// Wakeups can be spurious, so the condition has to be checked in a loop.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+
+        // Unlock only once we are queued, so a notification can't be missed
+        self.waiters.wait_with(move || drop(guard));
+
+        mutex.lock()
+    }
+
+    /// Blocks until `condition` returns false for the protected data.
+    pub fn wait_while<'a, T: ?Sized, F>(&self, mut guard: MutexGuard<'a, T>, mut condition: F)
+            -> MutexGuard<'a, T> where F: FnMut(&mut T) -> bool {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
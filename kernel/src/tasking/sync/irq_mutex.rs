@@ -0,0 +1,74 @@
+use core::ops::{Deref, DerefMut};
+use spin;
+use x86::shared::flags::{flags, FLAGS_IF};
+use x86::shared::irq;
+
+/// A spinlock that disables interrupts while it is held, so it can be shared
+/// between tasks and interrupt handlers without deadlocking. Keep critical
+/// sections short, and don't block or allocate while holding it.
+pub struct IrqMutex<T: ?Sized> {
+    inner: spin::Mutex<T>
+}
+
+pub struct IrqMutexGuard<'a, T: ?Sized + 'a> {
+    // Fields are dropped in order, so the lock is released before interrupts
+    // are enabled again
+    guard: spin::MutexGuard<'a, T>,
+    _interrupts: RestoreInterrupts
+}
+
+struct RestoreInterrupts {
+    enabled: bool
+}
+
+impl Drop for RestoreInterrupts {
+    fn drop(&mut self) {
+        if self.enabled {
+            unsafe { irq::enable() };
+        }
+    }
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(data: T) -> IrqMutex<T> {
+        IrqMutex { inner: spin::Mutex::new(data) }
+    }
+}
+
+impl<T: ?Sized> IrqMutex<T> {
+    pub fn lock(&self) -> IrqMutexGuard<T> {
+        let interrupts = disable_interrupts();
+
+        IrqMutexGuard { guard: self.inner.lock(), _interrupts: interrupts }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<T>> {
+        let interrupts = disable_interrupts();
+
+        self.inner.try_lock().map(|guard| IrqMutexGuard { guard: guard, _interrupts: interrupts })
+    }
+}
+
+fn disable_interrupts() -> RestoreInterrupts {
+    let enabled = flags().contains(FLAGS_IF);
+
+    if enabled {
+        unsafe { irq::disable() };
+    }
+
+    RestoreInterrupts { enabled: enabled }
+}
+
+impl<'a, T: ?Sized> Deref for IrqMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &*self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for IrqMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.guard
+    }
+}
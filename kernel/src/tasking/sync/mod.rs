@@ -0,0 +1,16 @@
+//! Locks for task context that park waiting tasks on a wait queue instead of
+//! spinning, and a spinlock that can be shared with interrupt handlers.
+//!
+//! Only `IrqMutex` may be used from interrupt handlers, since the others block.
+
+pub use self::condvar::Condvar;
+pub use self::irq_mutex::{IrqMutex, IrqMutexGuard};
+pub use self::mutex::{Mutex, MutexGuard};
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use self::semaphore::Semaphore;
+
+mod condvar;
+mod irq_mutex;
+mod mutex;
+mod rwlock;
+mod semaphore;
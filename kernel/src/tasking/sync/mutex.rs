@@ -0,0 +1,87 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use tasking::WaitQueue;
+
+/// A mutual exclusion lock that blocks the waiting task instead of spinning.
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>
+}
+
+impl<T> Mutex<T> {
+    pub fn new(data: T) -> Mutex<T> {
+        Mutex {
+            locked: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data)
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        unsafe { self.data.into_inner() }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Blocks until the lock is available.
+    pub fn lock(&self) -> MutexGuard<T> {
+        if !self.acquire() {
+            self.waiters.wait_until(|| self.acquire());
+        }
+
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.acquire() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    fn acquire(&self) -> bool {
+        !self.locked.compare_and_swap(false, true, Ordering::Acquire)
+    }
+
+    fn release(&self) {
+        self.locked.store(false, Ordering::Release);
+        self.waiters.wake_one();
+    }
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// Used by `Condvar` to lock the mutex again after waiting
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.release();
+    }
+}
@@ -0,0 +1,133 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use tasking::WaitQueue;
+
+/// Set in `state` while a writer holds the lock, otherwise `state` is the
+/// number of readers
+const WRITER: usize = !(usize::max_value() >> 1);
+
+/// A reader-writer lock that blocks waiting tasks instead of spinning.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicUsize,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>
+}
+
+impl<T> RwLock<T> {
+    pub fn new(data: T) -> RwLock<T> {
+        RwLock {
+            state: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data)
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        unsafe { self.data.into_inner() }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Blocks until no writer holds the lock.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        if !self.acquire_read() {
+            self.waiters.wait_until(|| self.acquire_read());
+        }
+
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Blocks until nobody else holds the lock.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        if !self.acquire_write() {
+            self.waiters.wait_until(|| self.acquire_write());
+        }
+
+        RwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        if self.acquire_read() {
+            Some(RwLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if self.acquire_write() {
+            Some(RwLockWriteGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    fn acquire_read(&self) -> bool {
+        let mut state = self.state.load(Ordering::Acquire);
+
+        while state & WRITER == 0 {
+            let previous = self.state.compare_and_swap(state, state + 1, Ordering::Acquire);
+
+            if previous == state {
+                return true;
+            }
+            state = previous;
+        }
+
+        false
+    }
+
+    fn acquire_write(&self) -> bool {
+        self.state.compare_and_swap(0, WRITER, Ordering::Acquire) == 0
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        // Only a writer can be waiting, and only once all readers are gone
+        if self.lock.state.fetch_sub(1, Ordering::Release) == 1 {
+            self.lock.waiters.wake_all();
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        self.lock.waiters.wake_all();
+    }
+}
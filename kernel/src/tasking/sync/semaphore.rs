@@ -0,0 +1,50 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use tasking::WaitQueue;
+
+/// A counting semaphore. Releasing never blocks, so that can also be done
+/// from interrupt handlers.
+pub struct Semaphore {
+    count: AtomicUsize,
+    waiters: WaitQueue
+}
+
+impl Semaphore {
+    pub fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: AtomicUsize::new(count),
+            waiters: WaitQueue::new()
+        }
+    }
+
+    /// Blocks until the count is positive, then decrements it.
+    pub fn acquire(&self) {
+        if !self.try_acquire() {
+            self.waiters.wait_until(|| self.try_acquire());
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count.load(Ordering::SeqCst);
+
+        while count > 0 {
+            let previous = self.count.compare_and_swap(count, count - 1, Ordering::SeqCst);
+
+            if previous == count {
+                return true;
+            }
+            count = previous;
+        }
+
+        false
+    }
+
+    /// Increments the count and wakes up a waiting task.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.waiters.wake_one();
+    }
+
+    pub fn available(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
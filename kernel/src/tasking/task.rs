@@ -1,43 +1,257 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
+use alloc::String;
+use arch::memory::{self, Stack};
+use arch::pit;
 use arch::tasking::Context;
-use core::ops::Deref;
-use core::sync::atomic::AtomicUsize;
-use super::{tasks, exit, switch};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use super::{tasks, exit, Priority, DEFAULT_PRIORITY};
+use time::Duration;
+use super::wait_queue::WaitQueue;
 
 int_like!(TaskId, AtomicTaskId, usize, AtomicUsize);
 
-pub type TaskMain = fn();
+/// Called once when the task starts. `spawn` wraps the user's `FnOnce` in this.
+pub type TaskMain = Box<FnMut() + Send>;
+
+/// Passed to `exit` and collected by the parent task with `wait`
+pub type ExitStatus = i32;
+
+/// Unused stack memory is filled with this, so the deepest point a task's
+/// stack ever reached can be found later
+pub const STACK_PAINT: u8 = 0x57;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Runnable,
+    /// Parked on a wait queue until it is woken up
+    Blocked,
+    /// Waiting for the timer to reach a certain tick
+    Sleeping,
+    Finished
+}
+
+/// The scheduling state of a task. It lives outside of the task's lock, so
+/// that wait queues can wake tasks up from interrupt handlers.
+pub struct SharedState {
+    state: AtomicUsize,
+    /// Timer tick a sleeping task wakes up at
+    wake_tick: AtomicUsize
+}
+
+impl SharedState {
+    pub fn new() -> SharedState {
+        SharedState {
+            state: AtomicUsize::new(TaskState::Runnable as usize),
+            wake_tick: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn get(&self) -> TaskState {
+        match self.state.load(Ordering::SeqCst) {
+            0 => TaskState::Runnable,
+            1 => TaskState::Blocked,
+            2 => TaskState::Sleeping,
+            _ => TaskState::Finished
+        }
+    }
+
+    pub fn set(&self, state: TaskState) {
+        self.state.store(state as usize, Ordering::SeqCst);
+    }
+
+    pub fn sleep_until(&self, tick: usize) {
+        self.wake_tick.store(tick, Ordering::SeqCst);
+        self.set(TaskState::Sleeping);
+    }
+
+    /// The tick a sleeping task wakes up at
+    pub fn wake_tick(&self) -> Option<usize> {
+        match self.get() {
+            TaskState::Sleeping => Some(self.wake_tick.load(Ordering::SeqCst)),
+            _ => None
+        }
+    }
+
+    /// Makes a blocked or sleeping task runnable. Returns false if the task
+    /// wasn't waiting.
+    pub fn wake(&self) -> bool {
+        for &state in [TaskState::Blocked, TaskState::Sleeping].iter() {
+            let previous = self.state.compare_and_swap(state as usize,
+                                                       TaskState::Runnable as usize,
+                                                       Ordering::SeqCst);
+            if previous == state as usize {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether the task can run at the given timer tick, waking it up if its
+    /// sleep is over
+    pub fn is_runnable(&self, now: usize) -> bool {
+        match self.get() {
+            TaskState::Runnable => true,
+            TaskState::Sleeping if now >= self.wake_tick.load(Ordering::SeqCst) => self.wake(),
+            _ => false
+        }
+    }
+}
 
 pub struct Task {
     pub id: TaskId,
-    pub main: TaskMain,
+    pub name: String,
+    /// Timer tick the task was created at
+    pub created: usize,
+    /// The task that spawned this one, if any
+    pub parent: Option<TaskId>,
+    /// Taken out when the task starts. The mutex keeps `Task` `Sync`, since
+    /// the closure itself only needs to be `Send`.
+    pub main: Mutex<Option<TaskMain>>,
     pub context: Context,
-    pub finished: bool,
-    pub kernel_stack: Option<Box<[u8]>>
+    pub state: Arc<SharedState>,
+    /// Tasks waiting for this one to finish
+    pub exit_queue: Arc<WaitQueue>,
+    pub exit_status: ExitStatus,
+    pub kernel_stack: Option<Stack>,
+    /// Saved preemption nesting depth while the task is switched out
+    pub preempt_count: usize,
+    pub priority: Priority,
+    /// Timer ticks the task has spent running
+    pub cpu_ticks: usize,
+    /// CPU time weighted by priority, used by the fair scheduler
+    pub vruntime: usize
 }
 
 impl Task {
-    pub fn new(id: TaskId, main: TaskMain) -> Task {
-        Task { id: id, main: main, context: Context::new(), finished: false,
-               kernel_stack: None }
+    pub fn new(id: TaskId, name: String, main: TaskMain) -> Task {
+        Task { id: id, name: name, created: pit::ticks(), parent: None,
+               main: Mutex::new(Some(main)), context: Context::new(),
+               state: Arc::new(SharedState::new()), exit_queue: Arc::new(WaitQueue::new()),
+               exit_status: 0, kernel_stack: None, preempt_count: 0,
+               priority: DEFAULT_PRIORITY, cpu_ticks: 0, vruntime: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.get() == TaskState::Finished
     }
 
-    pub fn wait_for(&self) {
-        while !self.finished {
-            switch();
+    /// Charges the task for running `ticks` timer ticks. Higher priority
+    /// tasks are charged less, so the fair scheduler runs them more often.
+    pub fn account(&mut self, ticks: usize) {
+        self.cpu_ticks += ticks;
+        self.vruntime = self.vruntime_after(ticks);
+    }
+
+    /// What `vruntime` will be once the task has been charged for `ticks`
+    pub fn vruntime_after(&self, ticks: usize) -> usize {
+        self.vruntime + ticks * 256 / (self.priority as usize + 1)
+    }
+
+    /// The time the task has spent running
+    pub fn cpu_time(&self) -> Duration {
+        pit::ticks_to_duration(self.cpu_ticks)
+    }
+
+    /// The most stack space the task has ever used, in bytes. The primary
+    /// task runs on the boot stack, which isn't tracked.
+    pub fn stack_peak(&self) -> Option<usize> {
+        self.kernel_stack.as_ref().map(|stack| {
+            let mut address = stack.bottom();
+
+            while address < stack.top() && unsafe { *(address as *const u8) } == STACK_PAINT {
+                address += 1;
+            }
+
+            stack.top() - address
+        })
+    }
+
+    pub fn info(&self) -> TaskInfo {
+        TaskInfo {
+            id: self.id,
+            name: self.name.clone(),
+            parent: self.parent,
+            state: self.state.get(),
+            priority: self.priority,
+            created: pit::ticks_to_duration(self.created),
+            cpu_time: self.cpu_time(),
+            stack_size: self.kernel_stack.as_ref().map(|stack| stack.top() - stack.bottom()),
+            stack_peak: self.stack_peak()
+        }
+    }
+}
+
+/// A point in time record of a task, as returned by `tasking::snapshot`
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: String,
+    pub parent: Option<TaskId>,
+    pub state: TaskState,
+    pub priority: Priority,
+    /// Time since boot the task was created at
+    pub created: Duration,
+    pub cpu_time: Duration,
+    /// `None` for the primary task, which runs on the boot stack
+    pub stack_size: Option<usize>,
+    pub stack_peak: Option<usize>
+}
+
+impl fmt::Display for TaskInfo {
+    /// Formats the task as a row of a `ps`-style table
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}  ", self.id.into())?;
+
+        match self.parent {
+            Some(parent) => write!(f, "{:>6}  ", parent.into())?,
+            None => write!(f, "{:>6}  ", "-")?
+        }
+
+        let state = match self.state {
+            TaskState::Runnable => "runnable",
+            TaskState::Blocked => "blocked",
+            TaskState::Sleeping => "sleeping",
+            TaskState::Finished => "finished"
+        };
+        write!(f, "{:<8}  {:>4}  {:>5}.{:02}s  {:>5}.{:02}s  ", state, self.priority,
+               self.created.as_secs(), self.created.subsec_nanos() / 10_000_000,
+               self.cpu_time.as_secs(), self.cpu_time.subsec_nanos() / 10_000_000)?;
+
+        match (self.stack_peak, self.stack_size) {
+            (Some(peak), Some(size)) => write!(f, "{:>4}/{:>4}K  ", (peak + 1023) / 1024,
+                                               size / 1024)?,
+            _ => write!(f, "{:>10}  ", "-")?
+        }
+
+        write!(f, "{}", if self.name.is_empty() { "-" } else { &self.name[..] })
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if let Some(stack) = self.kernel_stack.take() {
+            memory::controller().free_stack(stack);
         }
     }
 }
 
 pub fn execute_task() {
-    let main = {
-        let tasks = tasks();
-        let current_lock = tasks.current()
-            .expect("Attempted to execute main function outside of current task!");
-        let current = current_lock.read();
-
-        current.deref().main
-    };
-    main();
-    exit();
+    {
+        let mut main = {
+            let tasks = tasks();
+            let current_lock = tasks.current()
+                .expect("Attempted to execute main function outside of current task!");
+            let current = current_lock.read();
+
+            let main = current.main.lock().take();
+            main.expect("Task main function already called!")
+        };
+        main();
+    }
+    // The main function has to be dropped first, since exit never returns
+    exit(0);
 }
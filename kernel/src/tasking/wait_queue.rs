@@ -0,0 +1,180 @@
+use alloc::arc::Arc;
+use alloc::VecDeque;
+use arch::interrupts;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use spin::{Mutex, MutexGuard};
+use super::{switch, current_state, disable_preemption, TaskId};
+use super::task::{SharedState, TaskState};
+
+/// `pending_wakeups` value meaning every waiter should be woken up
+const WAKE_ALL: usize = usize::max_value();
+
+struct Waiter {
+    id: TaskId,
+    state: Arc<SharedState>
+}
+
+/// A queue of blocked tasks waiting for something to happen. Waking tasks up
+/// never blocks or allocates, so it can be done from interrupt handlers.
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<Waiter>>,
+    /// Wakeups that came in while the queue was locked. Whoever holds the lock
+    /// handles them before unlocking.
+    pending_wakeups: AtomicUsize
+}
+
+impl WaitQueue {
+    pub fn new() -> WaitQueue {
+        WaitQueue {
+            waiters: Mutex::new(VecDeque::new()),
+            pending_wakeups: ATOMIC_USIZE_INIT
+        }
+    }
+
+    /// Blocks the current task until it is woken up. This can return early,
+    /// for example if there was no other task to switch to, so callers need to
+    /// check what they were waiting for in a loop, or use `wait_until`.
+    pub fn wait(&self) {
+        self.wait_with(|| {});
+    }
+
+    /// Like `wait`, but calls `f` once the current task is in the queue and
+    /// before it switches away, so that a wakeup caused by `f` can't be missed.
+    pub fn wait_with<F>(&self, f: F) where F: FnOnce() {
+        let (id, state) = current_state();
+
+        {
+            // See `wait_until`
+            let _guard = disable_preemption();
+
+            self.park(id, &state);
+            f();
+            switch();
+        }
+        self.unpark(id, &state);
+    }
+
+    /// Blocks the current task until `condition` returns true. Whoever makes
+    /// the condition true must wake the queue up afterwards.
+    pub fn wait_until<F>(&self, mut condition: F) where F: FnMut() -> bool {
+        let (id, state) = current_state();
+
+        // Once parked, the task is blocked. If the timer switched it out
+        // before it got to `switch`, a wakeup that had already come in would
+        // be lost, and the task would never run again. The preempt count is
+        // saved with the task, so other tasks still get preempted meanwhile.
+        let _guard = disable_preemption();
+
+        loop {
+            // Parking before checking the condition means a wakeup that comes
+            // in between can't get lost
+            self.park(id, &state);
+
+            if condition() {
+                self.unpark(id, &state);
+                return;
+            }
+
+            switch();
+        }
+    }
+
+    /// Wakes up the task that has been waiting the longest, if any.
+    pub fn wake_one(&self) {
+        self.wake(1);
+    }
+
+    /// Wakes up every waiting task.
+    pub fn wake_all(&self) {
+        self.wake(WAKE_ALL);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let waiters = self.waiters.lock();
+        let empty = waiters.is_empty();
+        self.unlock(waiters);
+        empty
+    }
+
+    fn wake(&self, count: usize) {
+        match self.waiters.try_lock() {
+            Some(waiters) => {
+                self.add_pending(count);
+                self.unlock(waiters);
+            }
+            // The lock holder will take care of it
+            None => self.add_pending(count)
+        }
+    }
+
+    fn add_pending(&self, count: usize) {
+        let mut pending = self.pending_wakeups.load(Ordering::SeqCst);
+
+        loop {
+            let new = pending.saturating_add(count);
+            let previous = self.pending_wakeups.compare_and_swap(pending, new, Ordering::SeqCst);
+
+            if previous == pending {
+                return;
+            }
+            pending = previous;
+        }
+    }
+
+    /// Marks the current task as blocked and adds it to the queue, unless it
+    /// is already waiting in it.
+    fn park(&self, id: TaskId, state: &Arc<SharedState>) {
+        let mut waiters = self.waiters.lock();
+
+        if waiters.iter().any(|waiter| waiter.id == id) {
+            state.set(TaskState::Blocked);
+            return self.unlock(waiters);
+        }
+
+        // Allocate now, since the heap can't be used with interrupts disabled
+        waiters.reserve(1);
+        let waiter = Waiter { id: id, state: state.clone() };
+
+        // If the timer switched away between blocking and queueing, nobody
+        // would ever wake us up
+        interrupts::without_interrupts(|| {
+            state.set(TaskState::Blocked);
+            waiters.push_back(waiter);
+        });
+
+        self.unlock(waiters);
+    }
+
+    /// Removes the current task from the queue, if it wasn't woken up, and
+    /// makes it runnable again.
+    fn unpark(&self, id: TaskId, state: &Arc<SharedState>) {
+        let mut waiters = self.waiters.lock();
+
+        waiters.retain(|waiter| waiter.id != id);
+        state.set(TaskState::Runnable);
+
+        self.unlock(waiters);
+    }
+
+    /// Handles the pending wakeups and unlocks the queue. Interrupts are
+    /// disabled meanwhile, so an interrupt handler can't add a wakeup after we
+    /// checked for them, but before the queue is unlocked.
+    fn unlock(&self, mut waiters: MutexGuard<VecDeque<Waiter>>) {
+        interrupts::without_interrupts(move || {
+            let mut pending = self.pending_wakeups.swap(0, Ordering::SeqCst);
+
+            while pending > 0 {
+                match waiters.pop_front() {
+                    Some(waiter) => {
+                        waiter.state.wake();
+                    }
+                    None => break
+                }
+
+                if pending != WAKE_ALL {
+                    pending -= 1;
+                }
+            }
+        });
+    }
+}
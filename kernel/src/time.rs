@@ -1,5 +1,281 @@
 use core::cmp::min;
 use core::fmt;
+use core::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+const NANOS_PER_MILLI: u32 = 1_000_000;
+const NANOS_PER_MICRO: u32 = 1_000;
+
+/// A span of time, with nanosecond precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Duration {
+    secs: u64,
+    nanos: u32
+}
+
+impl Duration {
+    pub fn new(secs: u64, nanos: u32) -> Duration {
+        Duration {
+            secs: secs + (nanos / NANOS_PER_SEC) as u64,
+            nanos: nanos % NANOS_PER_SEC
+        }
+    }
+
+    pub fn from_secs(secs: u64) -> Duration {
+        Duration { secs: secs, nanos: 0 }
+    }
+
+    pub fn from_millis(millis: u64) -> Duration {
+        Duration {
+            secs: millis / 1000,
+            nanos: (millis % 1000) as u32 * NANOS_PER_MILLI
+        }
+    }
+
+    pub fn from_micros(micros: u64) -> Duration {
+        Duration {
+            secs: micros / 1_000_000,
+            nanos: (micros % 1_000_000) as u32 * NANOS_PER_MICRO
+        }
+    }
+
+    pub fn from_nanos(nanos: u64) -> Duration {
+        Duration {
+            secs: nanos / NANOS_PER_SEC as u64,
+            nanos: (nanos % NANOS_PER_SEC as u64) as u32
+        }
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.secs
+    }
+
+    /// The whole duration in milliseconds, rounded down
+    pub fn as_millis(&self) -> u64 {
+        self.secs * 1000 + (self.nanos / NANOS_PER_MILLI) as u64
+    }
+
+    /// The whole duration in nanoseconds. Saturates after about 584 years.
+    pub fn as_nanos(&self) -> u64 {
+        self.secs.saturating_mul(NANOS_PER_SEC as u64).saturating_add(self.nanos as u64)
+    }
+
+    /// The fractional part of the duration, in milliseconds
+    pub fn subsec_millis(&self) -> u32 {
+        self.nanos / NANOS_PER_MILLI
+    }
+
+    /// The fractional part of the duration, in microseconds
+    pub fn subsec_micros(&self) -> u32 {
+        self.nanos / NANOS_PER_MICRO
+    }
+
+    /// The fractional part of the duration, in nanoseconds
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    pub fn checked_add(self, other: Duration) -> Option<Duration> {
+        let secs = match self.secs.checked_add(other.secs) {
+            Some(secs) => secs,
+            None => return None
+        };
+        let nanos = self.nanos + other.nanos;
+
+        if nanos >= NANOS_PER_SEC {
+            secs.checked_add(1).map(|secs| Duration { secs: secs, nanos: nanos - NANOS_PER_SEC })
+        } else {
+            Some(Duration { secs: secs, nanos: nanos })
+        }
+    }
+
+    /// Returns `None` if `other` is longer than `self`.
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        let secs = match self.secs.checked_sub(other.secs) {
+            Some(secs) => secs,
+            None => return None
+        };
+
+        if self.nanos >= other.nanos {
+            Some(Duration { secs: secs, nanos: self.nanos - other.nanos })
+        } else {
+            secs.checked_sub(1).map(|secs| {
+                Duration { secs: secs, nanos: self.nanos + NANOS_PER_SEC - other.nanos }
+            })
+        }
+    }
+
+    pub fn checked_mul(self, factor: u32) -> Option<Duration> {
+        let nanos = self.nanos as u64 * factor as u64;
+        let carry = nanos / NANOS_PER_SEC as u64;
+
+        self.secs.checked_mul(factor as u64)
+            .and_then(|secs| secs.checked_add(carry))
+            .map(|secs| Duration { secs: secs, nanos: (nanos % NANOS_PER_SEC as u64) as u32 })
+    }
+
+    /// Returns `None` if `divisor` is zero.
+    pub fn checked_div(self, divisor: u32) -> Option<Duration> {
+        if divisor == 0 {
+            return None;
+        }
+
+        let secs = self.secs / divisor as u64;
+        let carry = self.secs % divisor as u64;
+        let nanos = (carry * NANOS_PER_SEC as u64 + self.nanos as u64) / divisor as u64;
+
+        Some(Duration { secs: secs, nanos: nanos as u32 })
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        self.checked_add(other).expect("Overflow when adding durations")
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        self.checked_sub(other).expect("Overflow when subtracting durations")
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Duration) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<u32> for Duration {
+    type Output = Duration;
+
+    fn mul(self, factor: u32) -> Duration {
+        self.checked_mul(factor).expect("Overflow when multiplying a duration")
+    }
+}
+
+impl Div<u32> for Duration {
+    type Output = Duration;
+
+    fn div(self, divisor: u32) -> Duration {
+        self.checked_div(divisor).expect("Divide by zero when dividing a duration")
+    }
+}
+
+impl fmt::Display for Duration {
+    /// Uses the largest unit that keeps the value at least one, with three
+    /// decimals, e.g. `1.500s` or `20.000ms`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.secs > 0 {
+            write!(f, "{}.{:03}s", self.secs, self.nanos / NANOS_PER_MILLI)
+        } else if self.nanos >= NANOS_PER_MILLI {
+            write!(f, "{}.{:03}ms", self.nanos / NANOS_PER_MILLI,
+                   self.nanos % NANOS_PER_MILLI / NANOS_PER_MICRO)
+        } else if self.nanos >= NANOS_PER_MICRO {
+            write!(f, "{}.{:03}us", self.nanos / NANOS_PER_MICRO, self.nanos % NANOS_PER_MICRO)
+        } else {
+            write!(f, "{}ns", self.nanos)
+        }
+    }
+}
+
+/// A point in time, measured by a monotonic clock that starts at boot. Unlike
+/// `DateTime`, it isn't affected by changes to the wall clock, so it is the
+/// right tool for measuring how long something took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    /// Nanoseconds since boot
+    nanos: u64
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        use arch::clock::monotonic_nanos;
+
+        Instant { nanos: monotonic_nanos() }
+    }
+
+    /// The time since boot
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.nanos)
+    }
+
+    /// The time passed from `earlier` to `self`. Panics if `earlier` is later
+    /// than `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).expect("`earlier` is later than `self`")
+    }
+
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.nanos.checked_sub(earlier.nanos).map(Duration::from_nanos)
+    }
+
+    /// The time passed since `self`
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        self.nanos.checked_add(duration.as_nanos()).map(|nanos| Instant { nanos: nanos })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        self.nanos.checked_sub(duration.as_nanos()).map(|nanos| Instant { nanos: nanos })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("Overflow when adding a duration to an instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("Overflow when subtracting a duration from an instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, other: Instant) -> Duration {
+        self.duration_since(other)
+    }
+}
+
+impl fmt::Display for Instant {
+    /// Formats the instant as the time since boot
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{}", self.since_boot())
+    }
+}
 
 pub struct DateTime {
     pub year: u32,
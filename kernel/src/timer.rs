@@ -0,0 +1,254 @@
+//! One-shot and periodic callbacks driven by the timer tick. Callbacks run in
+//! a dedicated kernel task rather than in the interrupt handler, so they may
+//! block and allocate, but a slow callback delays all the others.
+
+use alloc::boxed::Box;
+use alloc::{BTreeMap, BinaryHeap};
+use arch::pit;
+use core::cmp::{self, Ordering};
+use core::sync::atomic::{self, AtomicUsize};
+use spin::{Mutex, MutexGuard, Once};
+use tasking::{self, WaitQueue};
+use time::Duration;
+
+int_like!(TimerId, usize);
+
+type Callback = Box<FnMut() + Send>;
+
+/// `NEXT_DEADLINE` value meaning no timer is pending
+const NO_DEADLINE: usize = usize::max_value();
+
+struct Timer {
+    callback: Callback,
+    /// Ticks between runs of a periodic timer
+    period: Option<usize>
+}
+
+/// Orders the heap by deadline, earliest first
+#[derive(PartialEq, Eq)]
+struct Deadline {
+    tick: usize,
+    id: TimerId
+}
+
+impl Ord for Deadline {
+    fn cmp(&self, other: &Deadline) -> Ordering {
+        other.tick.cmp(&self.tick).then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Deadline) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct Timers {
+    /// Pending timers. A timer is taken out while its callback runs.
+    timers: BTreeMap<TimerId, Timer>,
+    /// Deadlines of the pending timers. Cancelled timers are only removed
+    /// from `timers`, their deadlines are skipped once they come up.
+    deadlines: BinaryHeap<Deadline>,
+    /// The timer whose callback is running, and whether it was cancelled
+    /// meanwhile
+    running: Option<(TimerId, bool)>,
+    next_id: usize
+}
+
+static TIMERS: Once<Mutex<Timers>> = Once::new();
+
+/// Earliest tick a timer is due at, so the timer interrupt can check whether
+/// the timer task needs waking without taking any locks
+static NEXT_DEADLINE: AtomicUsize = AtomicUsize::new(NO_DEADLINE);
+
+static TIMER_QUEUE: Once<WaitQueue> = Once::new();
+
+/// Cancels the timer when asked to. Dropping the handle leaves it running.
+pub struct TimerHandle {
+    id: TimerId
+}
+
+impl TimerHandle {
+    pub fn id(&self) -> TimerId {
+        self.id
+    }
+
+    /// Stops the timer. Returns false if it has already fired (for one-shot
+    /// timers) or was cancelled before. If the callback is running right now,
+    /// it finishes, but doesn't run again.
+    pub fn cancel(self) -> bool {
+        let callback = {
+            let mut timers = timers();
+
+            match timers.timers.remove(&self.id) {
+                Some(timer) => Some(timer.callback),
+                None => {
+                    return match timers.running {
+                        Some((id, ref mut cancelled)) if id == self.id && !*cancelled => {
+                            *cancelled = true;
+                            true
+                        }
+                        _ => false
+                    };
+                }
+            }
+        };
+
+        // The callback might own resources that lock `TIMERS` when dropped
+        drop(callback);
+        true
+    }
+}
+
+/// Starts the task running the timer callbacks. Must be called after tasking
+/// has been initialized.
+pub fn init() {
+    assert_has_not_been_called!("timer::init must be called only once");
+
+    TIMERS.call_once(|| {
+        Mutex::new(Timers {
+            timers: BTreeMap::new(),
+            deadlines: BinaryHeap::new(),
+            running: None,
+            next_id: 0
+        })
+    });
+    TIMER_QUEUE.call_once(WaitQueue::new);
+    tasking::spawn_named("timer", run_timers);
+
+    ok!("Timers initialized.");
+}
+
+/// Calls `callback` once, after at least `delay`.
+pub fn after<F>(delay: Duration, callback: F) -> TimerHandle
+        where F: FnOnce() + Send + 'static {
+    let mut callback = Some(callback);
+
+    add(delay, None, Box::new(move || {
+        if let Some(callback) = callback.take() {
+            callback();
+        }
+    }))
+}
+
+/// Calls `callback` every `period`, starting one `period` from now.
+pub fn every<F>(period: Duration, callback: F) -> TimerHandle
+        where F: FnMut() + Send + 'static {
+    add(period, Some(period), Box::new(callback))
+}
+
+fn timers() -> MutexGuard<'static, Timers> {
+    TIMERS.try().expect("Timers not initialized!").lock()
+}
+
+/// A timer can't fire before the next tick
+fn to_ticks(duration: Duration) -> usize {
+    cmp::max(pit::duration_to_ticks(duration), 1)
+}
+
+fn add(delay: Duration, period: Option<Duration>, callback: Callback) -> TimerHandle {
+    let deadline = pit::ticks().saturating_add(to_ticks(delay));
+    let timer = Timer { callback: callback, period: period.map(to_ticks) };
+
+    let mut timers = timers();
+    let id = TimerId::from(timers.next_id);
+    timers.next_id += 1;
+
+    timers.timers.insert(id, timer);
+    timers.deadlines.push(Deadline { tick: deadline, id: id });
+    timers.update_next_deadline();
+
+    TimerHandle { id: id }
+}
+
+impl Timers {
+    fn update_next_deadline(&mut self) {
+        let next = self.deadlines.peek().map_or(NO_DEADLINE, |deadline| deadline.tick);
+        NEXT_DEADLINE.store(next, atomic::Ordering::SeqCst);
+    }
+
+    /// Takes out the next timer that is due at `now`, if any.
+    fn pop_expired(&mut self, now: usize) -> Option<(TimerId, usize, Timer)> {
+        loop {
+            match self.deadlines.peek() {
+                Some(deadline) if deadline.tick <= now => {}
+                _ => return None
+            }
+
+            let deadline = self.deadlines.pop().expect("Timer deadline disappeared!");
+
+            // Cancelled timers have no entry anymore
+            if let Some(timer) = self.timers.remove(&deadline.id) {
+                self.update_next_deadline();
+                return Some((deadline.id, deadline.tick, timer));
+            }
+        }
+    }
+}
+
+/// The tick the next timer is due at, if any
+pub fn next_deadline() -> Option<usize> {
+    match NEXT_DEADLINE.load(atomic::Ordering::SeqCst) {
+        NO_DEADLINE => None,
+        tick => Some(tick)
+    }
+}
+
+/// Called from the timer interrupt on every tick.
+pub fn tick() {
+    if NEXT_DEADLINE.load(atomic::Ordering::SeqCst) <= pit::ticks() {
+        if let Some(queue) = TIMER_QUEUE.try() {
+            queue.wake_all();
+        }
+    }
+}
+
+fn run_timers() {
+    let queue = TIMER_QUEUE.try().expect("Timer queue not initialized!");
+
+    loop {
+        queue.wait_until(|| NEXT_DEADLINE.load(atomic::Ordering::SeqCst) <= pit::ticks());
+
+        let now = pit::ticks();
+
+        loop {
+            let next = {
+                let mut timers = timers();
+                let next = timers.pop_expired(now);
+
+                if let Some((id, _, _)) = next {
+                    timers.running = Some((id, false));
+                } else {
+                    timers.update_next_deadline();
+                }
+                next
+            };
+
+            let (id, deadline, mut timer) = match next {
+                Some(next) => next,
+                None => break
+            };
+
+            (timer.callback)();
+
+            let mut timers = timers();
+            let cancelled = match timers.running.take() {
+                Some((_, cancelled)) => cancelled,
+                None => false
+            };
+
+            if let (Some(period), false) = (timer.period, cancelled) {
+                // Stay on the original schedule, unless we fell behind by more
+                // than a whole period
+                let mut next_deadline = deadline.saturating_add(period);
+                if next_deadline <= now {
+                    next_deadline = now.saturating_add(period);
+                }
+
+                timers.timers.insert(id, timer);
+                timers.deadlines.push(Deadline { tick: next_deadline, id: id });
+                timers.update_next_deadline();
+            }
+        }
+    }
+}